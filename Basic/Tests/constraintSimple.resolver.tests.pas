@@ -0,0 +1,597 @@
+unit constraintSimple.resolver.tests;
+
+interface
+
+uses
+  System.Types, System.Math,
+  DUnitX.TestFramework,
+  constraintSimple.resolver;
+
+type
+  [TestFixture]
+  TChainSolverTests = class
+  private
+    //Straight chain along X, tail (last point) on the origin.
+    function MakeChain(aLinks : integer; aLength : single) : TChainState;
+    function MaxLinkError(const aChain : TChainState) : single;
+  public
+    [Test]
+    procedure ReachableTargetConverges;
+    [Test]
+    procedure MorePassesLeaveLessError;
+    [Test]
+    procedure OutOfReachStretchesTowardTarget;
+    [Test]
+    procedure WithoutAnchorOnePassIsEnough;
+    [Test]
+    procedure LockedPointIsNeverMoved;
+    [Test]
+    procedure LimitAngleClampsAndKeepsLength;
+    [Test]
+    procedure LimitAngleLeavesSmallBends;
+    [Test]
+    procedure JointLimitsArePerJoint;
+  end;
+
+  [TestFixture]
+  TDistanceTests = class
+  public
+    [Test]
+    procedure PointIsMovedToTheDistance;
+    [Test]
+    procedure CoincidentPointUsesTheTieBreak;
+    [Test]
+    procedure NearlyCoincidentPointUsesTheTieBreak;
+    [Test]
+    procedure TieBreakIsUnitAndRepeatable;
+    [Test]
+    procedure TieBreakSpreadsPairs;
+  end;
+
+  [TestFixture]
+  TContainmentTests = class
+  public
+    [Test]
+    procedure InsideCircleKeepsInnerPoints;
+    [Test]
+    procedure InsideCircleBringsBackOuterPoints;
+    [Test]
+    procedure InsideAABBKeepsInnerPoints;
+    [Test]
+    procedure InsideAABBClampsEachAxis;
+  end;
+
+  [TestFixture]
+  TPointFToolTests = class
+  public
+    [Test]
+    procedure SetLengthKeepsTheDirection;
+    [Test]
+    procedure PerpendicularTurnsAQuarter;
+    [Test]
+    procedure ClampLengthOnlyShortens;
+    [Test]
+    procedure LerpGoesFromSelfToTarget;
+    [Test]
+    procedure MulIsComponentWise;
+  end;
+
+  [TestFixture]
+  TInvariantTests = class
+  public
+    [Test]
+    procedure DistanceIsExactOverASweep;
+    [Test]
+    procedure ZeroLengthVectorsGiveNoNaN;
+    [Test]
+    procedure SeparationNeverIncreasesOverlap;
+  end;
+
+  [TestFixture]
+  TCollisionTests = class
+  private
+    function Coincident(aCount : integer) : TBallState; //10 px balls, all on (5,5).
+  public
+    [Test]
+    procedure CoincidentBallsEndUpApart;
+    [Test]
+    procedure CoincidentLockedBallPushesTheOther;
+    [Test]
+    procedure ThreeCoincidentBallsSpreadOut;
+    [Test]
+    procedure SequentialOrderIsTheIndexOrder;
+    [Test]
+    procedure ShuffledOrderIsRepeatable;
+    [Test]
+    procedure ShuffledOrderVisitsEveryPairOnce;
+    [Test]
+    procedure JacobiDoesNotDependOnInputOrder;
+  end;
+
+implementation
+
+//Dense pile of 10 px balls in a 60 px square, on our own generator so it is the same everywhere. Ball 0 is locked.
+function MakePile(aCount : integer; aSeed : cardinal) : TBallState;
+var i : integer;
+    lX : cardinal;
+begin
+  setlength(result.Points,aCount);
+  setlength(result.Radii,aCount);
+  setlength(result.Locked,aCount);
+  for i := 0 to aCount-1 do begin
+    aSeed := cardinal((UInt64(aSeed) * 1664525 + 1013904223) and $FFFFFFFF);
+    lX := aSeed mod 60;
+    aSeed := cardinal((UInt64(aSeed) * 1664525 + 1013904223) and $FFFFFFFF);
+    result.Points[i] := PointF(lX,aSeed mod 60);
+    result.Radii[i] := 10;
+    result.Locked[i] := i = 0;
+  end;
+end;
+
+function TotalOverlap(const aBalls : TBallState) : single;
+var i, j : integer;
+    lOverlap : single;
+begin
+  result := 0;
+  for i := 0 to length(aBalls.Points)-1 do
+    for j := i+1 to length(aBalls.Points)-1 do begin
+      lOverlap := aBalls.Radii[i] + aBalls.Radii[j] - (aBalls.Points[j] - aBalls.Points[i]).Length;
+      if lOverlap > 0 then
+        result := result + lOverlap;
+    end;
+end;
+
+function IsFinitePoint(const aPoint : TPointF) : boolean;
+begin
+  result := not (IsNan(aPoint.X) or IsNan(aPoint.Y) or IsInfinite(aPoint.X) or IsInfinite(aPoint.Y));
+end;
+
+{ TChainSolverTests }
+
+function TChainSolverTests.MakeChain(aLinks: integer; aLength: single): TChainState;
+var i : integer;
+begin
+  setlength(result.Points,aLinks+1);
+  setlength(result.Lengths,aLinks+1);
+  setlength(result.Locked,aLinks+1);
+  setlength(result.Active,aLinks+1);
+  setlength(result.JointLimits,aLinks+1);
+  for i := 0 to aLinks do begin
+    result.Points[i] := PointF((aLinks-i)*aLength,0);
+    result.Lengths[i] := aLength;
+    result.Locked[i] := false;
+    result.Active[i] := true;
+    result.JointLimits[i] := 0;
+  end;
+end;
+
+function TChainSolverTests.MaxLinkError(const aChain: TChainState): single;
+var i : integer;
+begin
+  result := 0;
+  for i := 1 to length(aChain.Points)-1 do
+    result := Max(result,Abs((aChain.Points[i] - aChain.Points[i-1]).Length - aChain.Lengths[i]));
+end;
+
+procedure TChainSolverTests.ReachableTargetConverges;
+var lChain : TChainState;
+    lPasses : integer;
+begin
+  lChain := MakeChain(5,50);
+  lPasses := TConstraintResolver.SolveChain(lChain,PointF(120,80),true,PointF(0,0),20,0.5);
+  Assert.IsTrue(lPasses < 20,'early exit once the head is within tolerance');
+  Assert.IsTrue((lChain.Points[0] - PointF(120,80)).Length <= 0.5,'end effector error after the passes');
+  Assert.AreEqual(0.0,lChain.Points[5].Length,1e-4,'tail on the anchor');
+  Assert.AreEqual(0.0,MaxLinkError(lChain),1e-3,'links at rest length');
+end;
+
+procedure TChainSolverTests.MorePassesLeaveLessError;
+var lOne, lTen : TChainState;
+begin
+  //Tolerance 0 : no early exit, exactly the asked passes.
+  lOne := MakeChain(5,50);
+  lTen := MakeChain(5,50);
+  Assert.AreEqual(1,TConstraintResolver.SolveChain(lOne,PointF(60,150),true,PointF(0,0),1,0));
+  Assert.AreEqual(10,TConstraintResolver.SolveChain(lTen,PointF(60,150),true,PointF(0,0),10,0));
+  Assert.IsTrue((lTen.Points[0] - PointF(60,150)).Length < (lOne.Points[0] - PointF(60,150)).Length);
+  Assert.IsTrue((lTen.Points[0] - PointF(60,150)).Length <= 0.5);
+end;
+
+procedure TChainSolverTests.OutOfReachStretchesTowardTarget;
+var lChain : TChainState;
+    i : integer;
+begin
+  lChain := MakeChain(4,50);
+  Assert.AreEqual(0,TConstraintResolver.SolveChain(lChain,PointF(0,1000),true,PointF(0,0),20,0.5),'no pass when out of reach');
+  for i := 0 to 4 do begin
+    Assert.AreEqual(0.0,lChain.Points[i].X,1e-4,'on the anchor to target line');
+    Assert.AreEqual((4-i)*50.0,lChain.Points[i].Y,1e-3,'stretched at rest length');
+  end;
+end;
+
+procedure TChainSolverTests.WithoutAnchorOnePassIsEnough;
+var lChain : TChainState;
+begin
+  lChain := MakeChain(5,50);
+  Assert.AreEqual(1,TConstraintResolver.SolveChain(lChain,PointF(400,300),false,PointF(0,0),20,0.5));
+  Assert.AreEqual(0.0,(lChain.Points[0] - PointF(400,300)).Length,1e-4,'head on the target');
+  Assert.AreEqual(0.0,MaxLinkError(lChain),1e-3,'links at rest length');
+end;
+
+procedure TChainSolverTests.LockedPointIsNeverMoved;
+var lChain : TChainState;
+begin
+  lChain := MakeChain(5,50);
+  lChain.Locked[2] := true;
+  TConstraintResolver.SolveChain(lChain,PointF(100,200),true,PointF(0,0),20,0.5);
+  Assert.AreEqual(150.0,lChain.Points[2].X,1e-6);
+  Assert.AreEqual(0.0,lChain.Points[2].Y,1e-6);
+end;
+
+procedure TChainSolverTests.LimitAngleClampsAndKeepsLength;
+var lDir : TPointF;
+    lClamped : boolean;
+begin
+  //A right angle bend, limited to 45 degrees.
+  lDir := TConstraintResolver.LimitAngle(PointF(0,20),PointF(1,0),DegToRad(45),lClamped);
+  Assert.IsTrue(lClamped);
+  Assert.AreEqual(20.0,lDir.Length,1e-4,'length kept');
+  Assert.AreEqual(45.0,RadToDeg(ArcTan2(lDir.Y,lDir.X)),1e-3,'bent to the limit, on the same side');
+end;
+
+procedure TChainSolverTests.LimitAngleLeavesSmallBends;
+var lDir : TPointF;
+    lClamped : boolean;
+begin
+  lDir := TConstraintResolver.LimitAngle(PointF(10,-5),PointF(1,0),DegToRad(45),lClamped);
+  Assert.IsFalse(lClamped);
+  Assert.AreEqual(10.0,lDir.X,1e-6);
+  Assert.AreEqual(-5.0,lDir.Y,1e-6);
+end;
+
+procedure TChainSolverTests.JointLimitsArePerJoint;
+var lChain : TChainState;
+begin
+  //Square corners at joints 1 and 2, only joint 1 is limited (45 deg).
+  lChain := MakeChain(3,10);
+  lChain.Points := [PointF(0,0),PointF(10,0),PointF(10,10),PointF(0,10)];
+  lChain.JointLimits[1] := DegToRad(45);
+  setlength(lChain.Clamped,4);
+  lChain.LimitJoint(0,1,2);
+  lChain.LimitJoint(1,2,3);
+  Assert.IsTrue(lChain.Clamped[1]);
+  Assert.IsFalse(lChain.Clamped[2]);
+  Assert.AreEqual(10 + 10*Sqrt(0.5),lChain.Points[2].X,1e-3);
+  Assert.AreEqual(10*Sqrt(0.5),lChain.Points[2].Y,1e-3);
+  Assert.AreEqual(0.0,lChain.Points[3].X,0);
+  Assert.AreEqual(10.0,lChain.Points[3].Y,0);
+end;
+
+{ TDistanceTests }
+
+procedure TDistanceTests.PointIsMovedToTheDistance;
+var p : TPointF;
+begin
+  p := TConstraintResolver.Distance(PointF(30,40),PointF(0,0),10);
+  Assert.AreEqual(6.0,p.X,1e-4);
+  Assert.AreEqual(8.0,p.Y,1e-4);
+end;
+
+procedure TDistanceTests.CoincidentPointUsesTheTieBreak;
+var p, lExpected : TPointF;
+begin
+  //Two particles on the same spot : they must still end up apart, in a known direction.
+  p := TConstraintResolver.Distance(PointF(5,5),PointF(5,5),10,TConstraintResolver.TieBreak(2,3));
+  lExpected := PointF(5,5) + TConstraintResolver.TieBreak(2,3) * 10;
+  Assert.AreEqual(10.0,(p - PointF(5,5)).Length,1e-4);
+  Assert.AreEqual(lExpected.X,p.X,1e-4);
+  Assert.AreEqual(lExpected.Y,p.Y,1e-4);
+end;
+
+procedure TDistanceTests.NearlyCoincidentPointUsesTheTieBreak;
+var p : TPointF;
+begin
+  p := TConstraintResolver.Distance(PointF(5+cst_COINCIDENT_EPSILON/10,5),PointF(5,5),10,TConstraintResolver.TieBreak(2,3));
+  Assert.AreEqual(10.0,(p - PointF(5,5)).Length,1e-4);
+end;
+
+procedure TDistanceTests.TieBreakIsUnitAndRepeatable;
+var a, b : TPointF;
+begin
+  a := TConstraintResolver.TieBreak(4,7);
+  b := TConstraintResolver.TieBreak(4,7);
+  Assert.AreEqual(1.0,a.Length,1e-5);
+  Assert.AreEqual(a.X,b.X,0);
+  Assert.AreEqual(a.Y,b.Y,0);
+end;
+
+procedure TDistanceTests.TieBreakSpreadsPairs;
+begin
+  //Three particles on one spot : each pair is pushed its own way, not all along one axis.
+  Assert.IsTrue((TConstraintResolver.TieBreak(0,1) - TConstraintResolver.TieBreak(0,2)).Length > 0.5);
+  Assert.IsTrue((TConstraintResolver.TieBreak(0,2) - TConstraintResolver.TieBreak(1,2)).Length > 0.5);
+end;
+
+{ TContainmentTests }
+
+procedure TContainmentTests.InsideCircleKeepsInnerPoints;
+var p : TPointF;
+begin
+  p := TConstraintResolver.InsideCircle(PointF(103,96),PointF(100,100),10);
+  Assert.AreEqual(103.0,p.X,0);
+  Assert.AreEqual(96.0,p.Y,0);
+end;
+
+procedure TContainmentTests.InsideCircleBringsBackOuterPoints;
+var p : TPointF;
+begin
+  //Back on the rim, along the center to point line.
+  p := TConstraintResolver.InsideCircle(PointF(130,140),PointF(100,100),10);
+  Assert.AreEqual(106.0,p.X,1e-4);
+  Assert.AreEqual(108.0,p.Y,1e-4);
+end;
+
+procedure TContainmentTests.InsideAABBKeepsInnerPoints;
+var p : TPointF;
+begin
+  p := TConstraintResolver.InsideAABB(PointF(15,25),RectF(10,20,30,40));
+  Assert.AreEqual(15.0,p.X,0);
+  Assert.AreEqual(25.0,p.Y,0);
+end;
+
+procedure TContainmentTests.InsideAABBClampsEachAxis;
+var p : TPointF;
+begin
+  p := TConstraintResolver.InsideAABB(PointF(-5,25),RectF(10,20,30,40));
+  Assert.AreEqual(10.0,p.X,0);
+  Assert.AreEqual(25.0,p.Y,0);
+  p := TConstraintResolver.InsideAABB(PointF(50,60),RectF(10,20,30,40));
+  Assert.AreEqual(30.0,p.X,0);
+  Assert.AreEqual(40.0,p.Y,0);
+end;
+
+{ TPointFToolTests }
+
+procedure TPointFToolTests.SetLengthKeepsTheDirection;
+var p : TPointF;
+begin
+  p := PointF(3,4);
+  p.setLength(10);
+  Assert.AreEqual(6.0,p.X,1e-3);
+  Assert.AreEqual(8.0,p.Y,1e-3);
+end;
+
+procedure TPointFToolTests.PerpendicularTurnsAQuarter;
+var p : TPointF;
+begin
+  p := PointF(2,1).perpendicular;
+  Assert.AreEqual(-1.0,p.X,0);
+  Assert.AreEqual(2.0,p.Y,0);
+  Assert.AreEqual(0.0,p.DotProduct(PointF(2,1)),0);
+end;
+
+procedure TPointFToolTests.ClampLengthOnlyShortens;
+var p : TPointF;
+begin
+  p := PointF(30,40).clampLength(10);
+  Assert.AreEqual(6.0,p.X,1e-3);
+  Assert.AreEqual(8.0,p.Y,1e-3);
+  p := PointF(3,4).clampLength(10);
+  Assert.AreEqual(3.0,p.X,0);
+  Assert.AreEqual(4.0,p.Y,0);
+end;
+
+procedure TPointFToolTests.LerpGoesFromSelfToTarget;
+var p : TPointF;
+begin
+  p := PointF(0,0).lerp(PointF(10,20),0.25);
+  Assert.AreEqual(2.5,p.X,1e-6);
+  Assert.AreEqual(5.0,p.Y,1e-6);
+  p := PointF(1,2).lerp(PointF(10,20),0);
+  Assert.AreEqual(1.0,p.X,0);
+  Assert.AreEqual(2.0,p.Y,0);
+  p := PointF(1,2).lerp(PointF(10,20),1);
+  Assert.AreEqual(10.0,p.X,1e-6);
+  Assert.AreEqual(20.0,p.Y,1e-6);
+end;
+
+procedure TPointFToolTests.MulIsComponentWise;
+var p : TPointF;
+begin
+  p := PointF(2,3).mul(PointF(4,-5));
+  Assert.AreEqual(8.0,p.X,0);
+  Assert.AreEqual(-15.0,p.Y,0);
+end;
+
+{ TInvariantTests }
+
+procedure TInvariantTests.DistanceIsExactOverASweep;
+const cst_DISTANCES : array[0..4] of single = (0.5,1,10,250,1000);
+var ix, iy : integer;
+    d : single;
+    lAnchor, p : TPointF;
+begin
+  //Points all around the anchor, on it included (ix = iy = 0).
+  lAnchor := PointF(13,-7);
+  for d in cst_DISTANCES do
+    for ix := -5 to 5 do
+      for iy := -5 to 5 do begin
+        p := TConstraintResolver.Distance(lAnchor + PointF(ix*37.5,iy*-21.25),lAnchor,d);
+        Assert.AreEqual(d,(p - lAnchor).Length,d*1e-5 + 1e-4);
+      end;
+end;
+
+procedure TInvariantTests.ZeroLengthVectorsGiveNoNaN;
+var lBalls : TBallState;
+    lMoveA, lMoveB : TPointF;
+begin
+  Assert.IsTrue(IsFinitePoint(TConstraintResolver.Distance(PointF(5,5),PointF(5,5),10)));
+  Assert.IsTrue(IsFinitePoint(TConstraintResolver.Distance(PointF(5,5),PointF(5,5),0)));
+  Assert.IsTrue(IsFinitePoint(TConstraintResolver.ClosestOnSegment(PointF(1,2),PointF(5,5),PointF(5,5))));
+  Assert.IsTrue(IsFinitePoint(TConstraintResolver.OutsideCapsule(PointF(5,5),PointF(5,5),PointF(5,5),3)));
+  Assert.IsTrue(IsFinitePoint(PointF(0,0).clampLength(5)));
+
+  lBalls.Points := [PointF(5,5),PointF(5,5)];
+  lBalls.Radii := [10,10];
+  lBalls.Locked := [false,false];
+  Assert.IsTrue(TConstraintResolver.PairCorrection(lBalls,0,1,lMoveA,lMoveB));
+  Assert.IsTrue(IsFinitePoint(lMoveA));
+  Assert.IsTrue(IsFinitePoint(lMoveB));
+end;
+
+procedure TInvariantTests.SeparationNeverIncreasesOverlap;
+var lOrder : TSolverOrder;
+    lBalls : TBallState;
+    lSeed, lPile : cardinal;
+    lBefore : single;
+begin
+  for lOrder := Low(TSolverOrder) to High(TSolverOrder) do
+    for lPile := 0 to 49 do begin
+      lBalls := MakePile(12,lPile);
+      lBefore := TotalOverlap(lBalls);
+      lSeed := 7;
+      TConstraintResolver.SeparateBalls(lBalls,lOrder,lSeed);
+      Assert.IsTrue(TotalOverlap(lBalls) <= lBefore + 1e-3);
+    end;
+end;
+
+{ TCollisionTests }
+
+function TCollisionTests.Coincident(aCount: integer): TBallState;
+var i : integer;
+begin
+  setlength(result.Points,aCount);
+  setlength(result.Radii,aCount);
+  setlength(result.Locked,aCount);
+  for i := 0 to aCount-1 do begin
+    result.Points[i] := PointF(5,5);
+    result.Radii[i] := 10;
+    result.Locked[i] := false;
+  end;
+end;
+
+procedure TCollisionTests.CoincidentBallsEndUpApart;
+var lBalls : TBallState;
+    lSeed : cardinal;
+    lTieBreak : TPointF;
+begin
+  //Half the correction each, along the pair tie-break.
+  lBalls := Coincident(2);
+  lSeed := 7;
+  TConstraintResolver.SeparateBalls(lBalls,TSolverOrder.Sequential,lSeed);
+  lTieBreak := TConstraintResolver.TieBreak(0,1);
+  Assert.AreEqual(20.0,(lBalls.Points[1] - lBalls.Points[0]).Length,1e-3);
+  Assert.AreEqual(5 - lTieBreak.X*10,lBalls.Points[0].X,1e-3);
+  Assert.AreEqual(5 - lTieBreak.Y*10,lBalls.Points[0].Y,1e-3);
+  Assert.AreEqual(5 + lTieBreak.X*10,lBalls.Points[1].X,1e-3);
+  Assert.AreEqual(5 + lTieBreak.Y*10,lBalls.Points[1].Y,1e-3);
+end;
+
+procedure TCollisionTests.CoincidentLockedBallPushesTheOther;
+var lBalls : TBallState;
+    lSeed : cardinal;
+begin
+  lBalls := Coincident(2);
+  lBalls.Locked[0] := true;
+  lSeed := 7;
+  TConstraintResolver.SeparateBalls(lBalls,TSolverOrder.Sequential,lSeed);
+  Assert.AreEqual(5.0,lBalls.Points[0].X,0);
+  Assert.AreEqual(5.0,lBalls.Points[0].Y,0);
+  Assert.AreEqual(20.0,(lBalls.Points[1] - lBalls.Points[0]).Length,1e-3);
+end;
+
+procedure TCollisionTests.ThreeCoincidentBallsSpreadOut;
+var lBalls : TBallState;
+    lSeed : cardinal;
+    i, j, lPass : integer;
+begin
+  lBalls := Coincident(3);
+  lSeed := 7;
+  for lPass := 1 to 10 do
+    TConstraintResolver.SeparateBalls(lBalls,TSolverOrder.Sequential,lSeed);
+  for i := 0 to 2 do
+    for j := i+1 to 2 do
+      Assert.IsTrue((lBalls.Points[j] - lBalls.Points[i]).Length >= 20 - 1e-2);
+end;
+
+procedure TCollisionTests.SequentialOrderIsTheIndexOrder;
+var lOrder : TArray<integer>;
+    lSeed : cardinal;
+    i : integer;
+begin
+  lSeed := 7;
+  lOrder := TConstraintResolver.SolverOrder(10,TSolverOrder.Sequential,lSeed);
+  for i := 0 to 9 do
+    Assert.AreEqual(i,lOrder[i]);
+  Assert.IsTrue(lSeed = 7);
+end;
+
+procedure TCollisionTests.ShuffledOrderIsRepeatable;
+var a, b : TArray<integer>;
+    lSeedA, lSeedB : cardinal;
+    i : integer;
+begin
+  lSeedA := 7;
+  lSeedB := 7;
+  a := TConstraintResolver.SolverOrder(20,TSolverOrder.Shuffled,lSeedA);
+  b := TConstraintResolver.SolverOrder(20,TSolverOrder.Shuffled,lSeedB);
+  for i := 0 to 19 do
+    Assert.AreEqual(a[i],b[i]);
+  //The seed moves on : the next pass is shuffled differently.
+  Assert.IsTrue(lSeedA = lSeedB);
+  Assert.IsTrue(lSeedA <> 7);
+end;
+
+procedure TCollisionTests.ShuffledOrderVisitsEveryPairOnce;
+var lOrder : TArray<integer>;
+    lVisits : array[0..19,0..19] of integer;
+    lSeed : cardinal;
+    i, j : integer;
+begin
+  //SeparateBalls takes the pairs (lOrder[i],lOrder[j]) for i < j.
+  lSeed := 7;
+  lOrder := TConstraintResolver.SolverOrder(20,TSolverOrder.Shuffled,lSeed);
+  FillChar(lVisits,SizeOf(lVisits),0);
+  for i := 0 to 19 do
+    for j := i+1 to 19 do
+      inc(lVisits[Min(lOrder[i],lOrder[j]),Max(lOrder[i],lOrder[j])]);
+  for i := 0 to 19 do
+    for j := i+1 to 19 do
+      Assert.AreEqual(1,lVisits[i,j]);
+end;
+
+procedure TCollisionTests.JacobiDoesNotDependOnInputOrder;
+var lBalls, lReversed : TBallState;
+    lSeed : cardinal;
+    i, lLast : integer;
+begin
+  //Same pile listed backward. No two balls of this pile share a spot, so the tie-break plays no part.
+  lBalls := MakePile(12,1);
+  lLast := length(lBalls.Points)-1;
+  setlength(lReversed.Points,lLast+1);
+  setlength(lReversed.Radii,lLast+1);
+  setlength(lReversed.Locked,lLast+1);
+  for i := 0 to lLast do begin
+    lReversed.Points[i] := lBalls.Points[lLast-i];
+    lReversed.Radii[i] := lBalls.Radii[lLast-i];
+    lReversed.Locked[i] := lBalls.Locked[lLast-i];
+  end;
+
+  lSeed := 7;
+  TConstraintResolver.SeparateBalls(lBalls,TSolverOrder.Jacobi,lSeed);
+  TConstraintResolver.SeparateBalls(lReversed,TSolverOrder.Jacobi,lSeed);
+  for i := 0 to lLast do begin
+    Assert.AreEqual(lBalls.Points[i].X,lReversed.Points[lLast-i].X,1e-3);
+    Assert.AreEqual(lBalls.Points[i].Y,lReversed.Points[lLast-i].Y,1e-3);
+  end;
+end;
+
+initialization
+  TDUnitX.RegisterTestFixture(TChainSolverTests);
+  TDUnitX.RegisterTestFixture(TDistanceTests);
+  TDUnitX.RegisterTestFixture(TContainmentTests);
+  TDUnitX.RegisterTestFixture(TPointFToolTests);
+  TDUnitX.RegisterTestFixture(TInvariantTests);
+  TDUnitX.RegisterTestFixture(TCollisionTests);
+
+end.
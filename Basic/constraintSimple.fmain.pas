@@ -5,11 +5,65 @@ interface
 uses
   System.SysUtils, System.Types, System.UITypes, System.Classes, System.Variants,
   FMX.Types, FMX.Controls, FMX.Forms, FMX.Graphics, FMX.Dialogs, FMX.Objects,
-  FMX.Controls.Presentation, FMX.StdCtrls, System.Math,
-  GS.Geometry.Direction;
+  FMX.Controls.Presentation, FMX.StdCtrls, System.Math, System.Diagnostics, System.IOUtils, System.JSON,
+  System.Rtti, FMX.Platform,
+  GS.Geometry.Direction, constraintSimple.resolver;
 
 type
   TConstraintScene = (basicDistance,SeparateCollision,DistanceChain);
+  //Invalid scene content (file, clipboard) : reported to the user, the current scene is left untouched.
+  ESceneError = class(Exception);
+  TColorMode = (Original,Speed,Density,Stress);
+  TBackgroundTheme = (Light,Dark,Gradient,Grid,Checker);
+  //What happens to particles pushed out of the window (plus cst_WORLD_MARGIN).
+  TBoundaryPolicy = (None,Clamp,Wrap,Despawn);
+  TBoundaryPolicies = set of TBoundaryPolicy;
+
+  //Named phase timings for the F3 overlay, as rolling averages over mouse moves.
+  TPhaseProfiler = class
+  private
+    FWatch : TStopwatch;
+    FCurrent : integer;
+    FNames : TArray<string>;
+    FAverages : TArray<double>;
+    FFrame : TArray<double>; //current move, a phase can run several times (solver iterations).
+    function PhaseIndex(const aName : string) : integer;
+    procedure EndPhase;
+  public
+    constructor Create;
+    procedure Clear;
+    procedure BeginPhase(const aName : string);
+    procedure EndFrame;
+    function Total : double;
+    property Names : TArray<string> read FNames;
+    property Averages : TArray<double> read FAverages;
+  end;
+
+  //Last positions of a particle, oldest overwritten first.
+  TTrail = record
+  private
+    FPoints : TArray<TPointF>;
+    FHead, FCount : integer;
+  public
+    procedure Clear;
+    procedure Push(const aPoint : TPointF);
+    function Point(aAge : integer) : TPointF; //0 is the newest.
+    property Count : integer read FCount;
+  end;
+
+  //Static wall drawn with Alt+drag in the collision scene, balls are kept out of it.
+  TColliderSegment = record
+    A, B : TPointF;
+  end;
+
+  //Per move measures, to compare solver changes on numbers rather than on looks.
+  TSceneDiagnostics = record
+    Kinetic : single; //sum of v^2/2, unit mass, velocity in pixels per move.
+    MaxViolation, AvgViolation : single; //distance constraint error, in pixels.
+    MaxPenetration : single; //deepest overlap between two particles, in pixels.
+  end;
+
+  TCCircle = class;
 
   TFMain = class(TForm)
     Selection1: TSelection;
@@ -23,18 +77,140 @@ type
     TrackBar1: TTrackBar;
     cbBallCollision: TCheckBox;
     TimerGravity: TTimer;
+    RectangleProfiler: TRectangle;
+    PaintBoxProfiler: TPaintBox;
+    LabelProfiler: TLabel;
+    PaintBoxOverlay: TPaintBox;
+    RectangleInspector: TRectangle;
+    LabelInspector: TLabel;
+    LabelStatus: TLabel;
+    TimerStatus: TTimer;
+    TimerRecord: TTimer;
+    PaintBoxGizmos: TPaintBox;
+    TimerSmoothing: TTimer;
+    LabelSceneHelp: TLabel;
     procedure FormCreate(Sender: TObject);
+    procedure FormDestroy(Sender: TObject);
     procedure CornerButton2Click(Sender: TObject);
+    procedure FormMouseMove(Sender: TObject; Shift: TShiftState; X, Y: Single);
+    procedure FormMouseDown(Sender: TObject; Button: TMouseButton; Shift: TShiftState; X, Y: Single);
+    procedure FormMouseUp(Sender: TObject; Button: TMouseButton; Shift: TShiftState; X, Y: Single);
+    procedure FormMouseWheel(Sender: TObject; Shift: TShiftState; WheelDelta: Integer; var Handled: Boolean);
+    procedure FormKeyDown(Sender: TObject; var Key: Word; var KeyChar: WideChar; Shift: TShiftState);
+    procedure FormKeyUp(Sender: TObject; var Key: Word; var KeyChar: WideChar; Shift: TShiftState);
+    procedure PaintBoxProfilerPaint(Sender: TObject; Canvas: TCanvas);
+    procedure PaintBoxOverlayPaint(Sender: TObject; Canvas: TCanvas);
+    procedure PaintBoxGizmosPaint(Sender: TObject; Canvas: TCanvas);
+    procedure TimerStatusTimer(Sender: TObject);
+    procedure TimerRecordTimer(Sender: TObject);
+    procedure TimerSmoothingTimer(Sender: TObject);
 
     function getRandomColor : TAlphaColor;
   private
     FCurrentScene : TConstraintScene;
+    FSceneMouseMove : TMouseMoveEvent;
+    FProfiler : TPhaseProfiler;
+    FShowMotion : boolean;
+    FSelected : integer;
+    FColorMode : TColorMode;
+    FTheme : TBackgroundTheme;
+    FRecordFolder : string;
+    FRecordFrame : integer;
+    FHideMenus : boolean;
+    FMousePos : TPointF;
+    FSpawning, FErasing : boolean;
+    FSpawnTick : integer;
+    FScissors : boolean;
+    FFabrikAnchor : TPointF;
+    FDraggingAnchor : boolean;
+    FIterations : integer;
+    FFabrikIterations : integer;
+    FSmoothing : boolean;
+    FRawMousePos : TPointF; //cursor position, FMousePos lags behind it when smoothing.
+    FLog : TStreamWriter;
+    FLogEvery, FMoveCount : integer;
+    FRecoveredCount : integer; //NaN/infinite positions reset since startup.
+    FDiagnostics : TSceneDiagnostics;
+    FDiagLog : TStreamWriter;
+    FBoundary : array[TConstraintScene] of TBoundaryPolicy;
+    FSolverOrder : TSolverOrder;
+    FOrderSeed : cardinal;
+    FCutLinks : TArray<boolean>; //FCutLinks[i] : link between chain balls i-1 and i was cut.
+    FLinkScale : TArray<single>; //FLinkScale[i] : rest length of link i, relative to the link distance.
+    FTapered : boolean;
+    FJointLimit : single; //FABRIK bend limit J gives every joint (and new ones), degrees, 0 : free rope.
+    FJointLimits : TArray<single>; //per joint bend limit in degrees, shift+J changes the inspected one.
+    FJointClamped : TArray<boolean>; //joints held at the limit during the last pass, drawn in the gizmos.
+    FGrabbed : integer; //particle held by the Ctrl+drag spring, -1 if none.
+    FBoxSelecting : boolean;
+    FBoxStart, FBoxEnd : TPointF; //rubber band corners, in form coordinates.
+    FBoxSelection : TArray<integer>; //scene circle indices picked by the last rubber band.
+    FColliders : TArray<TColliderSegment>;
+    FDrawingCollider : boolean;
+    FColliderStart, FColliderEnd : TPointF;
     procedure SetConstaintScene(const Value: TConstraintScene);
+    procedure SetSelected(const Value: integer);
+    procedure UpdateProfilerOverlay;
+    procedure UpdateInspector;
+    function ConstraintsOf(aIndex : integer) : string;
+    function CircleAt(aPoint : TPointF; aFirst : integer = 0) : integer;
+    function PickCircle(const aPoint : TPointF; aFirst : integer = 0) : integer;
+    function LinkStress(aIndex : integer) : single;
+    function LinkDistance(aIndex : integer) : single;
+    procedure ResizeChainLinks(aCount : integer);
+    procedure ApplyTaper;
+    function CircleStress(aIndex : integer) : single;
+    procedure ApplyColorMode;
+    procedure PaintBackground(Canvas: TCanvas);
+    function InkColor : TAlphaColor;
+    function CaptureFrame : TBitmap;
+    procedure SaveScreenshot;
+    procedure ToggleRecording;
+    procedure UpdateMenus;
+    procedure OpenTrajectoryLog;
+    procedure RecoverInvalidParticles;
+    procedure CheckInvariants;
+    procedure ApplyBoundaryPolicy;
+    function ComputeDiagnostics : TSceneDiagnostics;
+    procedure LogTrajectories;
+    procedure MoveFabrikAnchor(const aOffset : TPointF);
+    procedure ApplyGrabSpring;
+    //Scene circle queries, by index in SceneCircles. aFirst = 1 skips the mouse-driven circle.
+    function NearestCircle(const aPoint : TPointF; aFirst : integer = 0) : integer;
+    function CirclesWithin(const aCenter : TPointF; aRadius : single; aFirst : integer = 0) : TArray<integer>;
+    function CirclesInRect(const aRect : TRectF; aFirst : integer = 0) : TArray<integer>;
+    procedure SeparateBalls(const balls : TArray<TCCircle>);
+    procedure PushOutOfColliders(const balls : TArray<TCCircle>);
+    procedure SpawnAndErase;
+    procedure FreeCircle(aIndex : integer);
+    procedure PinBoxSelection;
+    procedure DeleteBoxSelection;
+    procedure Explode(const aCenter : TPointF);
+    procedure CutLinks(const aFrom, aTo : TPointF);
+    function LinkActive(aIndex : integer) : boolean;
+    function ChainIsFree : boolean;
     function GetCircles(Index: integer): TCircle;
+    function ChainBalls : TArray<TCCircle>;
+    procedure AddChainLink;
+    procedure RemoveChainLink;
+    function SceneCircles : TArray<TCCircle>;
+    procedure PaintMotion(Canvas: TCanvas);
     { Private declarations }
   public
     { Public declarations }
     procedure clearScene;
+    procedure StepScene; //solves the scene again for the current target, after a parameter change.
+    procedure ShowStatus(const aText : string);
+
+    //Scene description (particles, links, options) as JSON, for hand authoring and sharing.
+    function SceneToJSON : TJSONObject;
+    procedure SceneFromJSON(aJSON : TJSONObject);
+    procedure SaveSceneToFile(const aFileName : string);
+    procedure LoadSceneFromFile(const aFileName : string);
+    procedure SceneFromText(const aText, aSource : string);
+    procedure CopySceneToClipboard;
+    procedure PasteSceneFromClipboard;
+
     function addCircle(aRadius : single; aPosition : TPointF; const afillColor : TAlphaColor) : TCircle;
 
     //Specific scene mouse move.
@@ -46,6 +222,8 @@ type
     property Circles[Index : integer] : TCircle read GetCircles;
 
     property Scene : TConstraintScene read FCurrentScene Write SetConstaintScene;
+    //Index of the inspected circle, -1 if none.
+    property Selected : integer read FSelected Write SetSelected;
   end;
 
 
@@ -53,48 +231,129 @@ type
   //A little bit better TCircle for pascal. Original JS is very readable and consise on this point.
   TCCircle = class(TCircle)
   private
+    FPrevPos : TPointF;
+    FPrevRotation : single;
+    FBaseColor : TAlphaColor;
+    FPinned : boolean;
     function GetPos: TPointF;
     procedure SetPos(const Value: TPointF);
     function GetRadius: single;
+    function GetVelocity: TPointF;
+    function GetAngularVelocity: single;
+    function GetRotation: single;
+    procedure SetRotation(const Value: single);
+    procedure SetPinned(const Value: boolean);
+    procedure SetRadius(const Value: single);
+  public
+    Trail : TTrail;
+    procedure storePrevPos; //velocity (and angular velocity) is measured from here.
+    property velocity : TPointF read GetVelocity;
+    property prevPos : TPointF read FPrevPos;
+    property angularVelocity : single read GetAngularVelocity; //degrees per move.
+    property baseColor : TAlphaColor read FBaseColor; //color given at setup, before any color mode.
+    property pinned : boolean read FPinned write SetPinned; //pinned circles are never moved by constraints.
   published
     procedure setup(aRadius : single; aPos : TPointF; const afillColor : TAlphaColor);
     property pos : TPointF read GetPos Write SetPos;
-    property radius : single read GetRadius;
-  end;
-
-  //Add powerfull "pointf.length" (vectored length form paperJs)
-  TPointFTool = record helper for TPointf
-    procedure setLength(aNewLength : single); //vertor grow.
-  end;
-
-  TConstraintResolver = class
-    class function Distance(point, anchor: TPointF; distance: Double): TPointF;
+    property radius : single read GetRadius write SetRadius;
+    property rotation : single read GetRotation write SetRotation; //degrees, around the center.
   end;
 
 var
   FMain: TFMain;
 
 Const cst_SEPARATECOLL_BALL_COUNT = 40;
-      cst_CHAIN_BALL_COUNT = 10;
+      cst_CHAIN_BALL_COUNT = 10; //initial count, + and - change it.
+      cst_CHAIN_BALL_MIN = 2;
+      cst_CHAIN_BALL_MAX = 40;
+      cst_CHAIN_BALL_RADIUS = 15;
+      cst_JOINT_LIMITS : array[0..4] of single = (0,60,45,30,15); //J and shift+J cycle through them, degrees.
+      cst_TAPER_END = 0.4; //tapered chain : head link length and radius, relative to the tail ones.
+      cst_LINK_STRESS_FULLSCALE = 0.5; //relative link stretch drawn fully red.
+      cst_TRAIL_LENGTH = 40;
+      cst_VELOCITY_ARROW_SCALE = 3;
+      cst_SPEED_FULLSCALE = 20; //displacement per move drawn fully red.
+      cst_DENSITY_FULLSCALE = 6; //neighbour count drawn fully red.
+      cst_COLORMODE_NAMES : array[TColorMode] of string = ('original','speed','density','stress');
+      cst_THEME_NAMES : array[TBackgroundTheme] of string = ('light','dark','gradient','grid','checker');
+      cst_THEME_CELL = 40; //grid and checker square size.
+      cst_WHEEL_STEP = 2; //radius or link distance change per wheel notch.
+      cst_MAIN_RADIUS_MIN = 20;
+      cst_MAIN_RADIUS_MAX = 200;
+      cst_SPAWN_EVERY_MOVES = 3; //spawn rate while dragging in the collision scene.
+      cst_ERASER_REACH = 20; //balls centered this far beyond the main circle rim are erased.
+      cst_PICK_REACH = 10; //collision scene : a click picks a ball whose rim is this close to the main circle.
+      cst_ANCHOR_GRIP = 12; //mouse distance to grab the FABRIK anchor.
+      cst_GRAB_REACH = 10; //Ctrl+click grabs the nearest particle whose rim is this close.
+      cst_GRAB_STIFFNESS = 0.3; //share of the particle to cursor distance closed per move.
+      cst_GRAB_MAX_PULL = 40; //spring displacement per move at most, a flick can't throw the particle.
+      cst_BOX_MIN_SIZE = 4; //smaller shift drags are clicks : they inspect instead of selecting.
+      cst_COLLIDER_HALF_WIDTH = 3; //walls are capsules : segment plus this radius.
+      cst_COLLIDER_MIN_LENGTH = 10; //shorter Alt drags draw nothing.
+      cst_ANCHOR_NUDGE = 5; //arrow key step, x4 with shift.
+      cst_MOUSE_SMOOTHING = 0.25; //share of the remaining distance to the cursor covered per step.
+      cst_ITERATIONS_MAX = 20; //collision solver passes per mouse move.
+      cst_FABRIK_ITERATIONS = 10; //default forward/backward passes per mouse move.
+      cst_FABRIK_TOLERANCE = 0.5; //chain head distance to the mouse that stops the passes.
+      cst_WORLD_MARGIN = 200; //particles may leave the window by this much before the boundary policy applies.
+      cst_SOLVER_ORDER_NAMES : array[TSolverOrder] of string = ('sequential','shuffled','jacobi');
+      cst_SOLVER_SEED = 20230304;
+      cst_BOUNDARY_NAMES : array[TBoundaryPolicy] of string = ('none','clamp','wrap','despawn');
+      //Policies W cycles through. Only the collision scene can lose balls, and moving a single chain ball breaks its links.
+      cst_BOUNDARY_POLICIES : array[TConstraintScene] of TBoundaryPolicies = (
+        [TBoundaryPolicy.None,TBoundaryPolicy.Clamp,TBoundaryPolicy.Wrap],
+        [TBoundaryPolicy.None,TBoundaryPolicy.Clamp,TBoundaryPolicy.Wrap,TBoundaryPolicy.Despawn],
+        [TBoundaryPolicy.None]);
+      cst_LINK_TOLERANCE = 0.5; //debug check : allowed link length error when nothing fights the chain pass.
+      cst_EXPLOSION_RADIUS = 250;
+      cst_EXPLOSION_PUSH = 120; //displacement at the center, fading to 0 at the radius.
+      cst_SCENE_NAMES : array[TConstraintScene] of string = ('basic','collision','chain');
+      cst_SCENE_TITLES : array[TConstraintScene] of string = ('Basic distance','Separate collision','Distance chain');
+      cst_SCENE_DESCRIPTIONS : array[TConstraintScene] of string = (
+        'A ball kept inside the main circle by a distance constraint.',
+        'Balls pushed apart pair by pair, the main circle shoves them away.',
+        'Each ball stays at the link distance of the previous one. FABRIK holds the tail on an anchor.');
+      cst_SCENE_HELP : array[TConstraintScene] of string = (
+        'Mouse : drag the main circle' + sLineBreak +
+        'Wheel : main circle radius' + sLineBreak +
+        'Shift drag : box select, P : pin/unpin the selection' + sLineBreak +
+        'W : out of bounds policy (none, clamp, wrap)',
+        'Left drag : spawn balls, shift click : inspect' + sLineBreak +
+        'Shift drag : box select, P : pin/unpin, Delete : delete the selection' + sLineBreak +
+        'Alt drag : draw a wall, Backspace : remove the last one' + sLineBreak +
+        'Delete : eraser, W : out of bounds policy (none, clamp, wrap, despawn)' + sLineBreak +
+        '[ / ] : iterations, O : pass order',
+        'Hold X and drag : cut links' + sLineBreak +
+        'Anchor cross or arrows : move the FABRIK anchor' + sLineBreak +
+        '{ / } : FABRIK passes, wheel : link distance' + sLineBreak +
+        '+ / - : add/remove a link at the tail, T : taper' + sLineBreak +
+        'J : joint limits, shift+J : inspected joint (orange : at its limit)' + sLineBreak +
+        'Shift drag : box select, P : pin/unpin the selection');
+      cst_SCENE_FILTER = 'Constraint scene (*.json)|*.json';
+      cst_SCREENSHOT_FOLDER = 'screenshots';
+      cst_RECORD_FOLDER = 'recordings';
+      cst_RECORD_SCALE = 0.5; //recorded frames are downsampled to keep the sequence small.
+      cst_PROFILER_SMOOTHING = 0.1; //weight of the newest sample in the rolling averages.
+      cst_PROFILER_COLORS : array[0..3] of TAlphaColor = (TAlphaColors.Royalblue, TAlphaColors.Orange,
+                                                          TAlphaColors.Seagreen, TAlphaColors.Crimson);
 
 
 implementation
 
-class function TConstraintResolver.Distance(point, anchor: TPointF; distance: Double): TPointF;
-begin
-  Result := (point - anchor).Normalize * distance + anchor;
-end;
-
 {$R *.fmx}
 
 function TFMain.addCircle(aRadius: single; aPosition: TPointF; const afillColor : TAlphaColor): TCircle;
 begin
-  result := TCircle.Create(Self);
+  result := TCCircle.Create(Self);
   result.HitTest := false;
   AddObject(result);
   TCCircle(result).setup(aRadius,aPosition,afillColor);
 
+  PaintBoxGizmos.BringToFront;
   Selection1.BringToFront;
+  LabelSceneHelp.BringToFront;
+  RectangleProfiler.BringToFront;
+  RectangleInspector.BringToFront;
 end;
 
 procedure TFMain.clearScene;
@@ -116,53 +375,623 @@ end;
 
 procedure TFMain.FormCreate(Sender: TObject);
 begin
+  FProfiler := TPhaseProfiler.Create;
+  FSelected := -1;
+  FGrabbed := -1;
+  //Kept across scene switches, moved with the mouse or the arrow keys.
+  FFabrikAnchor := pointf(ClientWidth/2,ClientHeight/2);
+  FIterations := 1;
+  FFabrikIterations := cst_FABRIK_ITERATIONS;
+  FOrderSeed := cst_SOLVER_SEED;
   SelectionConstraintChainSubMenu.Visible := false;
   CornerButton1.OnClick(CornerButton1);
+
+  //constraintSimple -scene myScene.json
+  var lSceneFile : string;
+  if FindCmdLineSwitch('scene',lSceneFile,true,[clstValueNextParam]) then
+    LoadSceneFromFile(lSceneFile);
+
+  OpenTrajectoryLog;
 end;
 
-procedure TFMain.FormMouseMove_DistanceConstraintChain(Sender: TObject;
+procedure TFMain.FormDestroy(Sender: TObject);
+begin
+  FreeAndNil(FLog);
+  FreeAndNil(FDiagLog);
+  FreeAndNil(FProfiler);
+end;
+
+procedure TFMain.OpenTrajectoryLog;
+var lFile, lEvery : string;
+begin
+  //constraintSimple -log trajectories.csv [-logdiag diagnostics.csv] [-logevery 2]
+  FLogEvery := 1;
+  if FindCmdLineSwitch('logevery',lEvery,true,[clstValueNextParam]) then
+    FLogEvery := Max(StrToIntDef(lEvery,1),1);
+
+  //A bad path is reported, the demo then runs without that log.
+  if FindCmdLineSwitch('log',lFile,true,[clstValueNextParam]) then
+    try
+      FLog := TStreamWriter.Create(lFile,false,TEncoding.UTF8);
+      FLog.WriteLine('move,scene,particle,x,y');
+    except
+      on E: Exception do begin
+        FreeAndNil(FLog);
+        ShowMessage('Cannot open the trajectory log.' + sLineBreak + E.Message);
+      end;
+    end;
+
+  if FindCmdLineSwitch('logdiag',lFile,true,[clstValueNextParam]) then
+    try
+      FDiagLog := TStreamWriter.Create(lFile,false,TEncoding.UTF8);
+      FDiagLog.WriteLine('move,scene,kinetic,maxViolation,avgViolation,maxPenetration');
+    except
+      on E: Exception do begin
+        FreeAndNil(FDiagLog);
+        ShowMessage('Cannot open the diagnostics log.' + sLineBreak + E.Message);
+      end;
+    end;
+end;
+
+function IsFinitePoint(const aPoint : TPointF) : boolean;
+begin
+  result := not (IsNan(aPoint.X) or IsNan(aPoint.Y) or IsInfinite(aPoint.X) or IsInfinite(aPoint.Y));
+end;
+
+procedure TFMain.RecoverInvalidParticles;
+var lCircles : TArray<TCCircle>;
+    lFallback : TPointF;
+    i : integer;
+begin
+  //A single NaN spreads to every neighbour through the constraints, and the whole scene vanishes.
+  lFallback := FMousePos;
+  lCircles := SceneCircles;
+  for i := 0 to length(lCircles)-1 do begin
+    if IsFinitePoint(lCircles[i].pos) then begin
+      lFallback := lCircles[i].pos;
+      continue;
+    end;
+
+    {$IFDEF DEBUG}
+    Assert(false,Format('particle %d of the %s scene has an invalid position',[i,cst_SCENE_NAMES[FCurrentScene]]));
+    {$ENDIF}
+    //Release : put it back where it was before this move, or next to the previous valid particle.
+    if IsFinitePoint(lCircles[i].prevPos) then
+      lCircles[i].pos := lCircles[i].prevPos
+    else
+      lCircles[i].pos := lFallback;
+    lCircles[i].storePrevPos;
+    lFallback := lCircles[i].pos;
+    inc(FRecoveredCount);
+    ShowStatus(Format('warning : %d invalid particle positions recovered',[FRecoveredCount]));
+  end;
+end;
+
+procedure TFMain.ApplyBoundaryPolicy;
+var lCircles : TArray<TCCircle>;
+    lWorld : TRectF;
+    lPos : TPointF;
+    i : integer;
+begin
+  if FBoundary[FCurrentScene] = TBoundaryPolicy.None then
+    exit;
+
+  lWorld := RectF(-cst_WORLD_MARGIN,-cst_WORLD_MARGIN,ClientWidth+cst_WORLD_MARGIN,ClientHeight+cst_WORLD_MARGIN);
+  lCircles := SceneCircles;
+  //Circle 0 follows the mouse, it is never out of bounds.
+  for i := length(lCircles)-1 downto 1 do begin
+    lPos := lCircles[i].pos;
+    if lWorld.Contains(lPos) then
+      continue;
+
+    case FBoundary[FCurrentScene] of
+      TBoundaryPolicy.Clamp :
+        lPos := TConstraintResolver.InsideAABB(lPos,lWorld);
+      TBoundaryPolicy.Wrap :
+        lPos := PointF(lPos.X - Floor((lPos.X - lWorld.Left) / lWorld.Width) * lWorld.Width,
+                       lPos.Y - Floor((lPos.Y - lWorld.Top) / lWorld.Height) * lWorld.Height);
+      TBoundaryPolicy.Despawn : begin
+        FreeCircle(i);
+        continue;
+      end;
+    end;
+    lCircles[i].pos := lPos;
+    lCircles[i].storePrevPos;
+  end;
+end;
+
+procedure TFMain.CheckInvariants;
+var lCircles : TArray<TCCircle>;
+    i : integer;
+    lLength : single;
+begin
+  lCircles := SceneCircles;
+  for i := 0 to length(lCircles)-1 do
+    Assert(lCircles[i].radius > 0,Format('%s scene : particle %d has a radius of %.2f',[cst_SCENE_NAMES[FCurrentScene],i,lCircles[i].radius]));
+  Assert((FSelected >= -1) and (FSelected < length(lCircles)),Format('inspected particle %d out of range',[FSelected]));
+  Assert((FGrabbed >= -1) and (FGrabbed < length(lCircles)),Format('grabbed particle %d out of range',[FGrabbed]));
+
+  if FCurrentScene <> TConstraintScene.DistanceChain then
+    exit;
+
+  Assert((length(FCutLinks) = length(lCircles)) and (length(FLinkScale) = length(lCircles))
+         and (length(FJointLimits) = length(lCircles)),
+         Format('%d cut flags, %d link lengths and %d joint limits for %d chain balls',
+                [length(FCutLinks),length(FLinkScale),length(FJointLimits),length(lCircles)]));
+  //Collision, pins and the grab spring legitimately stretch links.
+  if cbBallCollision.IsChecked or not ChainIsFree or (FGrabbed >= 0) then
+    exit;
+  for i := 1 to length(lCircles)-1 do begin
+    lLength := (lCircles[i].pos - lCircles[i-1].pos).Length;
+    Assert(Abs(lLength - LinkDistance(i)) <= cst_LINK_TOLERANCE,
+           Format('chain link %d is %.2f long instead of %.2f',[i,lLength,LinkDistance(i)]));
+  end;
+end;
+
+procedure TFMain.LogTrajectories;
+var lCircles : TArray<TCCircle>;
+    i : integer;
+begin
+  inc(FMoveCount);
+  if FMoveCount mod FLogEvery <> 0 then
+    exit;
+
+  if Assigned(FDiagLog) then
+    FDiagLog.WriteLine(Format('%d,%s,%.4f,%.4f,%.4f,%.4f',[FMoveCount,cst_SCENE_NAMES[FCurrentScene],
+                                                            FDiagnostics.Kinetic,FDiagnostics.MaxViolation,
+                                                            FDiagnostics.AvgViolation,FDiagnostics.MaxPenetration],TFormatSettings.Invariant));
+  if not Assigned(FLog) then
+    exit;
+
+  lCircles := SceneCircles;
+  for i := 0 to length(lCircles)-1 do
+    FLog.WriteLine(Format('%d,%s,%d,%.3f,%.3f',[FMoveCount,cst_SCENE_NAMES[FCurrentScene],i,
+                                               lCircles[i].pos.X,lCircles[i].pos.Y],TFormatSettings.Invariant));
+end;
+
+procedure TFMain.FormKeyDown(Sender: TObject; var Key: Word;
+  var KeyChar: WideChar; Shift: TShiftState);
+var lStep : single;
+    i : integer;
+begin
+  if ssCtrl in Shift then begin
+    case Key of
+      vkS : with TSaveDialog.Create(nil) do
+        try
+          Filter := cst_SCENE_FILTER;
+          DefaultExt := 'json';
+          if Execute then
+            SaveSceneToFile(FileName);
+        finally
+          Free;
+        end;
+      vkO : with TOpenDialog.Create(nil) do
+        try
+          Filter := cst_SCENE_FILTER;
+          if Execute then
+            LoadSceneFromFile(FileName);
+        finally
+          Free;
+        end;
+      vkC : CopySceneToClipboard;
+      vkV : PasteSceneFromClipboard;
+    end;
+    exit;
+  end;
+
+  case Key of
+    vkF1 : begin
+      FHideMenus := not FHideMenus;
+      UpdateMenus;
+    end;
+    vkEscape : begin
+      FBoxSelection := nil;
+      PaintBoxGizmos.Repaint;
+    end;
+    vkBack :
+      if length(FColliders) > 0 then begin
+        setlength(FColliders,length(FColliders)-1);
+        StepScene;
+        ShowStatus(Format('walls : %d',[length(FColliders)]));
+      end;
+    vkDelete :
+      if (FCurrentScene = TConstraintScene.SeparateCollision) and (length(FBoxSelection) > 0) then
+        DeleteBoxSelection
+      else if FCurrentScene = TConstraintScene.SeparateCollision then begin
+        FErasing := not FErasing;
+        if FErasing then
+          ShowStatus('eraser on')
+        else
+          ShowStatus('eraser off');
+        PaintBoxGizmos.Repaint;
+      end;
+    vkF12 : SaveScreenshot;
+    vkF11 : ToggleRecording;
+    vkF3 : begin
+      RectangleProfiler.Visible := not RectangleProfiler.Visible;
+      UpdateProfilerOverlay;
+    end;
+    vkLeft, vkUp, vkRight, vkDown :
+      if Selected>=0 then begin
+        if (Key = vkLeft) or (Key = vkUp) then
+          Selected := (Selected - 1 + length(SceneCircles)) mod length(SceneCircles)
+        else
+          Selected := (Selected + 1) mod length(SceneCircles);
+      end
+      else if (FCurrentScene = TConstraintScene.DistanceChain) and CheckBoxFabrick.IsChecked then begin
+        //Nothing inspected : arrows nudge the FABRIK anchor.
+        lStep := cst_ANCHOR_NUDGE;
+        if ssShift in Shift then
+          lStep := lStep*4;
+        case Key of
+          vkLeft : MoveFabrikAnchor(PointF(-lStep,0));
+          vkRight : MoveFabrikAnchor(PointF(lStep,0));
+          vkUp : MoveFabrikAnchor(PointF(0,-lStep));
+          vkDown : MoveFabrikAnchor(PointF(0,lStep));
+        end;
+      end;
+  end;
+
+  case UpCase(KeyChar) of
+    'E' :
+      Explode(FMousePos);
+    'P' :
+      PinBoxSelection;
+    '[', ']' : begin
+      if KeyChar = '[' then
+        FIterations := Max(FIterations-1,1)
+      else
+        FIterations := Min(FIterations+1,cst_ITERATIONS_MAX);
+      StepScene;
+      ShowStatus(Format('collision iterations : %d (%.3f ms per move)',[FIterations,FProfiler.Total]));
+    end;
+    '{', '}' : begin
+      if KeyChar = '{' then
+        FFabrikIterations := Max(FFabrikIterations-1,1)
+      else
+        FFabrikIterations := Min(FFabrikIterations+1,cst_ITERATIONS_MAX);
+      StepScene;
+      ShowStatus(Format('FABRIK iterations : %d',[FFabrikIterations]));
+    end;
+    'X' :
+      FScissors := FCurrentScene = TConstraintScene.DistanceChain;
+    'S' : begin
+      FSmoothing := not FSmoothing;
+      if FSmoothing then
+        ShowStatus('mouse smoothing on')
+      else begin
+        //The timer would keep easing the target after the filter is off.
+        TimerSmoothing.Enabled := false;
+        ShowStatus('mouse smoothing off');
+      end;
+    end;
+    'V' : begin
+      FShowMotion := not FShowMotion;
+      PaintBoxOverlay.Repaint;
+    end;
+    'M' : begin
+      if FColorMode = High(TColorMode) then
+        FColorMode := Low(TColorMode)
+      else
+        FColorMode := Succ(FColorMode);
+      ApplyColorMode;
+      ShowStatus('color mode : ' + cst_COLORMODE_NAMES[FColorMode]);
+    end;
+    'B' : begin
+      if FTheme = High(TBackgroundTheme) then
+        FTheme := Low(TBackgroundTheme)
+      else
+        FTheme := Succ(FTheme);
+      LabelStatus.TextSettings.FontColor := InkColor;
+      UpdateMenus;
+      PaintBoxOverlay.Repaint;
+      ShowStatus('background : ' + cst_THEME_NAMES[FTheme]);
+    end;
+    '+', '=', '-' :
+      if FCurrentScene = TConstraintScene.DistanceChain then begin
+        if KeyChar = '-' then
+          RemoveChainLink
+        else
+          AddChainLink;
+        UpdateMenus;
+        StepScene;
+        ShowStatus(Format('chain links : %d',[length(ChainBalls)-1]));
+      end;
+    'T' :
+      if FCurrentScene = TConstraintScene.DistanceChain then begin
+        FTapered := not FTapered;
+        ApplyTaper;
+        StepScene;
+        if FTapered then
+          ShowStatus('tapered chain')
+        else
+          ShowStatus('uniform chain');
+      end;
+    'J' :
+      if (FCurrentScene = TConstraintScene.DistanceChain) and (ssShift in Shift) then begin
+        //Only the joints between two links can bend.
+        if (Selected <= 0) or (Selected >= length(ChainBalls)-1) then begin
+          ShowStatus('click a joint to inspect it first, not a chain end');
+          exit;
+        end;
+        i := 0;
+        while (i < High(cst_JOINT_LIMITS)) and (cst_JOINT_LIMITS[i] <> FJointLimits[Selected]) do
+          inc(i);
+        FJointLimits[Selected] := cst_JOINT_LIMITS[(i+1) mod length(cst_JOINT_LIMITS)];
+        StepScene;
+        UpdateInspector;
+        if FJointLimits[Selected] > 0 then
+          ShowStatus(Format('joint #%d limit : %.0f deg',[Selected,FJointLimits[Selected]]))
+        else
+          ShowStatus(Format('joint #%d limit : off',[Selected]));
+      end
+      else if FCurrentScene = TConstraintScene.DistanceChain then begin
+        i := 0;
+        while (i < High(cst_JOINT_LIMITS)) and (cst_JOINT_LIMITS[i] <> FJointLimit) do
+          inc(i);
+        FJointLimit := cst_JOINT_LIMITS[(i+1) mod length(cst_JOINT_LIMITS)];
+        for i := 0 to length(FJointLimits)-1 do
+          FJointLimits[i] := FJointLimit;
+        StepScene;
+        if FJointLimit > 0 then
+          ShowStatus(Format('joint limit : %.0f deg',[FJointLimit]))
+        else
+          ShowStatus('joint limit : off');
+      end;
+    'O' : begin
+      if FSolverOrder = High(TSolverOrder) then
+        FSolverOrder := Low(TSolverOrder)
+      else
+        FSolverOrder := Succ(FSolverOrder);
+      FOrderSeed := cst_SOLVER_SEED;
+      StepScene;
+      ShowStatus('collision order : ' + cst_SOLVER_ORDER_NAMES[FSolverOrder]);
+    end;
+    'W' :
+      if FCurrentScene = TConstraintScene.DistanceChain then
+        ShowStatus('out of bounds particles : none, the links keep the chain together')
+      else begin
+        repeat
+          if FBoundary[FCurrentScene] = High(TBoundaryPolicy) then
+            FBoundary[FCurrentScene] := Low(TBoundaryPolicy)
+          else
+            FBoundary[FCurrentScene] := Succ(FBoundary[FCurrentScene]);
+        until FBoundary[FCurrentScene] in cst_BOUNDARY_POLICIES[FCurrentScene];
+        ShowStatus('out of bounds particles : ' + cst_BOUNDARY_NAMES[FBoundary[FCurrentScene]]);
+      end;
+  end;
+end;
+
+procedure TFMain.FormMouseDown(Sender: TObject; Button: TMouseButton;
   Shift: TShiftState; X, Y: Single);
 var i : integer;
-    balls : TArray<TCCircle>;
-    mousepos : TPointF;
 begin
-  setlength(balls,cst_CHAIN_BALL_COUNT);
-  for i := 0 to cst_CHAIN_BALL_COUNT-1 do
-    balls[i] := TCCircle(Circles[i]);
+  case Button of
+    TMouseButton.mbLeft :
+      //Ctrl grabs with the spring, Alt draws a wall, shift drags a selection box, then the FABRIK anchor grip.
+      //Collision scene : plain drag spawns balls, a shift click (no drag) still inspects.
+      if ssCtrl in Shift then begin
+        if FCurrentScene = TConstraintScene.SeparateCollision then
+          i := PickCircle(PointF(X,Y),1)
+        else begin
+          i := NearestCircle(PointF(X,Y),1);
+          if (i>0) and ((TCCircle(Circles[i]).pos - PointF(X,Y)).Length > TCCircle(Circles[i]).radius + cst_GRAB_REACH) then
+            i := -1;
+        end;
+        if i>0 then
+          FGrabbed := i;
+      end
+      else if (ssAlt in Shift) and (FCurrentScene = TConstraintScene.SeparateCollision) then begin
+        FDrawingCollider := true;
+        FColliderStart := PointF(X,Y);
+        FColliderEnd := FColliderStart;
+      end
+      else if ssShift in Shift then begin
+        FBoxSelecting := true;
+        FBoxStart := PointF(X,Y);
+        FBoxEnd := FBoxStart;
+      end
+      else if (FCurrentScene = TConstraintScene.DistanceChain) and CheckBoxFabrick.IsChecked
+         and ((PointF(X,Y) - FFabrikAnchor).Length <= cst_ANCHOR_GRIP) then
+        FDraggingAnchor := true
+      else if FCurrentScene = TConstraintScene.SeparateCollision then
+        FSpawning := true
+      else
+        Selected := CircleAt(PointF(X,Y));
+    TMouseButton.mbMiddle :
+      Explode(PointF(X,Y));
+    TMouseButton.mbRight : begin
+      //Circle 0 follows the mouse and can't be pinned : look under it.
+      i := PickCircle(PointF(X,Y),1);
+      if i>0 then begin
+        TCCircle(Circles[i]).pinned := not TCCircle(Circles[i]).pinned;
+        UpdateInspector;
+      end;
+    end;
+  end;
+end;
+
+procedure TFMain.FormMouseUp(Sender: TObject; Button: TMouseButton;
+  Shift: TShiftState; X, Y: Single);
+var lBox : TRectF;
+    lWall : TColliderSegment;
+begin
+  if (Button = TMouseButton.mbLeft) and FDrawingCollider then begin
+    FDrawingCollider := false;
+    lWall.A := FColliderStart;
+    lWall.B := PointF(X,Y);
+    if (lWall.B - lWall.A).Length >= cst_COLLIDER_MIN_LENGTH then begin
+      FColliders := FColliders + [lWall];
+      ShowStatus(Format('walls : %d',[length(FColliders)]));
+    end;
+    //Balls caught under the new wall are pushed out right away.
+    StepScene;
+  end;
 
-  mousepos := pointf(X,Y);
-  balls[0].pos := mousepos;
-  for i := 1 to length(balls)-1 do
-    balls[i].pos := TConstraintResolver.Distance(balls[i].pos,balls[i-1].pos,TrackBar1.Value);
+  if (Button = TMouseButton.mbLeft) and FBoxSelecting then begin
+    FBoxSelecting := false;
+    lBox := TRectF.Create(FBoxStart,PointF(X,Y),true);
+    if (lBox.Width < cst_BOX_MIN_SIZE) and (lBox.Height < cst_BOX_MIN_SIZE) then begin
+      FBoxSelection := nil;
+      //Collision scene : the cursor is always on circle 0, pick the ball next to it.
+      Selected := PickCircle(PointF(X,Y));
+    end
+    else begin
+      //Circle 0 follows the mouse : it is never part of a group.
+      FBoxSelection := CirclesInRect(lBox,1);
+      ShowStatus(Format('%d particles selected',[length(FBoxSelection)]));
+    end;
+  end;
+
+  if Button = TMouseButton.mbLeft then begin
+    FSpawning := false;
+    FDraggingAnchor := false;
+    FGrabbed := -1;
+    PaintBoxGizmos.Repaint;
+  end;
+end;
+
+procedure TFMain.FormMouseWheel(Sender: TObject; Shift: TShiftState;
+  WheelDelta: Integer; var Handled: Boolean);
+var lStep : single;
+    c : TCCircle;
+begin
+  lStep := Sign(WheelDelta) * cst_WHEEL_STEP;
+  case FCurrentScene of
+    basicDistance, SeparateCollision : begin
+      //Main circle radius : container in basic scene, repulsion in collision scene.
+      c := TCCircle(Circles[0]);
+      c.radius := EnsureRange(c.radius + lStep,cst_MAIN_RADIUS_MIN,cst_MAIN_RADIUS_MAX);
+      ShowStatus(Format('main radius : %.0f',[c.radius]));
+    end;
+    DistanceChain : begin
+      TrackBar1.Value := TrackBar1.Value + lStep;
+      ShowStatus(Format('link distance : %.0f',[TrackBar1.Value]));
+    end;
+  end;
+  StepScene;
+  Handled := true;
+end;
+
+procedure TFMain.StepScene;
+var c : TCCircle;
+begin
+  //FMousePos is already filtered : replays never go through the smoothing again.
+  if not Assigned(FSceneMouseMove) then
+    exit;
+
+  for c in SceneCircles do
+    c.storePrevPos;
+
+  ApplyGrabSpring;
+  FSceneMouseMove(Self,[],FMousePos.X,FMousePos.Y);
+  FProfiler.EndFrame;
+  RecoverInvalidParticles;
+  ApplyBoundaryPolicy;
+  {$IFDEF DEBUG}
+  CheckInvariants;
+  {$ENDIF}
+  if RectangleProfiler.Visible or Assigned(FDiagLog) then
+    FDiagnostics := ComputeDiagnostics;
+
+  //The scene may have added or removed circles.
+  for c in SceneCircles do
+    c.Trail.Push(c.pos);
+  LogTrajectories;
+  ApplyColorMode;
+  UpdateInspector;
+  UpdateProfilerOverlay;
+  PaintBoxOverlay.Repaint;
+  PaintBoxGizmos.Repaint;
+end;
+
+procedure TFMain.FormKeyUp(Sender: TObject; var Key: Word;
+  var KeyChar: WideChar; Shift: TShiftState);
+begin
+  if Key = vkX then
+    FScissors := false;
+end;
+
+procedure TFMain.FormMouseMove(Sender: TObject; Shift: TShiftState; X,
+  Y: Single);
+var lTarget : TPointF;
+begin
+  if not Assigned(FSceneMouseMove) then
+    exit;
+
+  //Scissors : the scene is frozen and the mouse path only cuts links.
+  if FScissors then begin
+    CutLinks(FMousePos,PointF(X,Y));
+    FMousePos := PointF(X,Y);
+    PaintBoxOverlay.Repaint;
+    PaintBoxGizmos.Repaint;
+    exit;
+  end;
+
+  //Rubber band or wall drawing : the scene is frozen until the button is released.
+  if FBoxSelecting then begin
+    FBoxEnd := PointF(X,Y);
+    PaintBoxGizmos.Repaint;
+    exit;
+  end;
+  if FDrawingCollider then begin
+    FColliderEnd := PointF(X,Y);
+    PaintBoxGizmos.Repaint;
+    exit;
+  end;
+
+  //Dragging the anchor : the chain head keeps its last target.
+  if FDraggingAnchor then begin
+    FFabrikAnchor := PointF(X,Y);
+    X := FMousePos.X;
+    Y := FMousePos.Y;
+  end
+  else if FSmoothing then begin
+    //Low pass filter : the target eases toward the cursor, the timer finishes the way once the mouse stops.
+    FRawMousePos := PointF(X,Y);
+    lTarget := FMousePos.lerp(FRawMousePos,cst_MOUSE_SMOOTHING);
+    X := lTarget.X;
+    Y := lTarget.Y;
+    TimerSmoothing.Enabled := (FRawMousePos - lTarget).Length > 0.5;
+  end;
+
+  FMousePos := PointF(X,Y);
+  StepScene;
+end;
 
-  //https://zalo.github.io/blog/constraints/#fabrik-chain
-  if CheckBoxFabrick.IsChecked then begin
-    balls[length(balls)-1].pos := pointf(ClientWidth/2,ClientHeight/2);
-    for i := length(balls)-1 downto 1 do
-      balls[i-1].pos := TConstraintResolver.Distance(balls[i-1].pos,balls[i].pos,TrackBar1.Value);
+procedure TFMain.FormMouseMove_DistanceConstraintChain(Sender: TObject;
+  Shift: TShiftState; X, Y: Single);
+var i, lLast : integer;
+    balls : TArray<TCCircle>;
+    lChain : TChainState;
+begin
+  balls := ChainBalls;
+  lLast := length(balls)-1;
+  setlength(lChain.Points,lLast+1);
+  setlength(lChain.Lengths,lLast+1);
+  setlength(lChain.Locked,lLast+1);
+  setlength(lChain.Active,lLast+1);
+  setlength(lChain.JointLimits,lLast+1);
+  for i := 0 to lLast do begin
+    lChain.Points[i] := balls[i].pos;
+    lChain.Lengths[i] := LinkDistance(i);
+    lChain.Locked[i] := balls[i].pinned;
+    lChain.Active[i] := LinkActive(i);
+    lChain.JointLimits[i] := DegToRad(FJointLimits[i]);
   end;
 
+  FProfiler.BeginPhase('FABRIK');
+  TConstraintResolver.SolveChain(lChain,PointF(X,Y),CheckBoxFabrick.IsChecked,FFabrikAnchor,
+                                 FFabrikIterations,cst_FABRIK_TOLERANCE);
+  FJointClamped := lChain.Clamped;
+  for i := 0 to lLast do
+    balls[i].pos := lChain.Points[i];
+
   //Perform ball collision.
   if cbBallCollision.IsChecked then begin
-    //separate balls
-    var j : integer;
-    var ToNext,lOffset : TPointf;
-    var lradius : double;
-    for i := 0 to length(balls)-1 do
-      for j := i to length(balls)-1 do begin
-        if balls[i] = balls[j] then
-          continue;
-
-        toNext := balls[j].pos - balls[i].pos;
-        lradius := balls[j].radius + balls[i].radius;
-        if toNext.Length <= lradius then begin
-          toNext.setLength(lradius);
-          loffset := balls[j].pos - balls[i].pos - toNext;
-          loffset := loffset/2;
-          balls[i].pos := balls[i].pos + loffset;
-          balls[j].pos := balls[j].pos - loffset;
-        end;
-      end;
+    FProfiler.BeginPhase('collision');
+    for i := 1 to FIterations do
+      SeparateBalls(balls);
   end;
 end;
 
@@ -177,20 +1006,19 @@ end;
 
 procedure TFMain.FormMouseMove_DistanceConstraint(Sender: TObject;
   Shift: TShiftState; X, Y: Single);
-var toNext : TPointf;
-    mousecoord : TPointf;
+var mousecoord : TPointf;
     circle, ball : TCCircle;
 begin
   circle := TCCircle(Circles[0]);
   ball := TCCircle(Circles[1]);
 
+  FProfiler.BeginPhase('distance');
   mousecoord := Pointf(x,y);
 
   Circle.pos := mousecoord;
 
-  toNext := mousecoord - ball.pos;
-  if toNext.Length>circle.radius-ball.radius then
-    ball.pos := TConstraintResolver.Distance(ball.pos,mousecoord,circle.radius-ball.radius);
+  if not ball.pinned then
+    ball.pos := TConstraintResolver.InsideCircle(ball.pos,mousecoord,circle.radius-ball.radius);
 end;
 
 procedure TFMain.FormMouseMove_SeparateCollision(Sender: TObject;
@@ -199,158 +1027,1485 @@ var toNext : TPointf;
     mousecoord : TPointf;
     circle,b : TCCircle;
     balls : TArray<TCCircle>;
-    i,j : integer;
-    lradius : single;
     loffset : TPointF;
-    lDir : TDirectionalObject;
+    i,j : integer;
 begin
   circle := TCCircle(Circles[0]);
-  setLength(balls,cst_SEPARATECOLL_BALL_COUNT);
-  for i := 1 to cst_SEPARATECOLL_BALL_COUNT do
-    balls[i-1] := TCCircle(Circles[i]);
-
   mousecoord := Pointf(x,y);
 
   Circle.pos := mousecoord;
+  SpawnAndErase;
+  balls := Copy(SceneCircles,1,MaxInt);
 
-  //Constraint for main circle.
-  for b in balls do begin
-    toNext := Circle.pos - b.pos;
-    if toNext.Length<circle.radius+b.radius then
-      toNext.setLength(circle.radius+b.radius);
-      loffset := circle.pos - b.pos - toNext;
-      b.pos := b.pos + loffset;
-  end;
-
-  //separate balls
-  for i := 0 to length(balls)-1 do
-    for j := i to length(balls)-1 do begin
-      if balls[i] = balls[j] then
+  for i := 1 to FIterations do begin
+    //Constraint for main circle.
+    FProfiler.BeginPhase('main push-out');
+    for j := 0 to length(balls)-1 do begin
+      b := balls[j];
+      //The grabbed ball follows its spring to the cursor, inside the main circle.
+      if b.pinned or (j+1 = FGrabbed) then
         continue;
+      toNext := Circle.pos - b.pos;
+      if toNext.Length < cst_COINCIDENT_EPSILON then
+        toNext := TConstraintResolver.TieBreak(0,j+1);
+      if toNext.Length<circle.radius+b.radius then
+        toNext.setLength(circle.radius+b.radius);
+        loffset := circle.pos - b.pos - toNext;
+        b.pos := b.pos + loffset;
+    end;
 
-      toNext := balls[j].pos - balls[i].pos;
-      lradius := balls[j].radius + balls[i].radius;
-      if toNext.Length <= lradius then begin
-        toNext.setLength(lradius);
-        loffset := balls[j].pos - balls[i].pos - toNext;
-        loffset := loffset/2;
-        balls[i].pos := balls[i].pos + loffset;
-        balls[j].pos := balls[j].pos - loffset;
-      end;
+    //separate balls
+    FProfiler.BeginPhase('pair resolution');
+    SeparateBalls(balls);
+
+    if length(FColliders) > 0 then begin
+      FProfiler.BeginPhase('walls');
+      PushOutOfColliders(balls);
     end;
+  end;
 end;
 
-function TFMain.GetCircles(Index: integer): TCircle;
-var i,c : integer;
-    l : TArray<TFmxObject>;
+procedure TFMain.PushOutOfColliders(const balls: TArray<TCCircle>);
+var b : TCCircle;
+    w : TColliderSegment;
 begin
-  l := Children.ToArray;
-  c := 0;
-  for I := Low(l) to High(l) do
-    if l[i] is TCircle then begin
-      if c=index then begin
-        result := TCircle(l[i]);
-        break;
-      end;
-      inc(c);
-    end;
+  for b in balls do
+    if not b.pinned then
+      for w in FColliders do
+        b.pos := TConstraintResolver.OutsideCapsule(b.pos,w.A,w.B,b.radius + cst_COLLIDER_HALF_WIDTH);
 end;
 
-function TFMain.getRandomColor: TAlphaColor;
+procedure TFMain.SeparateBalls(const balls: TArray<TCCircle>);
+var lBalls : TBallState;
+    i : integer;
 begin
-  result := TAlphaColorF.Create(Random(255)/255,Random(255)/255,Random(255)/255,1).ToAlphaColor
+  setlength(lBalls.Points,length(balls));
+  setlength(lBalls.Radii,length(balls));
+  setlength(lBalls.Locked,length(balls));
+  for i := 0 to length(balls)-1 do begin
+    lBalls.Points[i] := balls[i].pos;
+    lBalls.Radii[i] := balls[i].radius;
+    lBalls.Locked[i] := balls[i].pinned;
+  end;
+  TConstraintResolver.SeparateBalls(lBalls,FSolverOrder,FOrderSeed);
+  for i := 0 to length(balls)-1 do
+    balls[i].pos := lBalls.Points[i];
 end;
 
-procedure TFMain.SetConstaintScene(const Value: TConstraintScene);
-var i,xj,yj : integer;
+procedure TFMain.SpawnAndErase;
+var lMain : TCCircle;
+    lErased : TArray<integer>;
+    i : integer;
 begin
-  CornerButton1.IsPressed := false;
-  CornerButton2.IsPressed := false;
-  CornerButton3.IsPressed := false;
-  SelectionConstraintChainSubMenu.Visible := False;
-  OnMouseMove := nil;
+  lMain := TCCircle(Circles[0]);
 
-  clearScene;
-  case value  of
+  if FSpawning then begin
+    inc(FSpawnTick);
+    //Small random offset : the main circle pushes it out in a random direction.
+    if FSpawnTick mod cst_SPAWN_EVERY_MOVES = 0 then
+      addCircle(15,lMain.pos + PointF(Random-0.5,Random-0.5),getRandomColor);
+  end;
 
-    basicDistance: begin
-      CornerButton1.IsPressed := true;
-      addCircle(50,pointF(400,400),TAlphaColors.White);
-      addCircle(15,pointF(400,400),TAlphaColors.Black);
-      OnMouseMove := FormMouseMove_DistanceConstraint;
-    end;
+  if FErasing then begin
+    //Highest index first : freeing a ball does not move the ones still to erase.
+    lErased := CirclesWithin(lMain.pos,lMain.radius + cst_ERASER_REACH,1);
+    for i := length(lErased)-1 downto 0 do
+      FreeCircle(lErased[i]);
+  end;
+end;
 
-    SeparateCollision: begin
-      CornerButton2.IsPressed := true;
-      addCircle(50,pointF(400,400),TAlphaColors.White);
-      xj := 0;
-      yj := xj;
-      for i := 1 to cst_SEPARATECOLL_BALL_COUNT do begin
-        if i Mod 10 = 0 then begin
-          xj := 0;
-          inc(yj);
-        end;
-        addCircle(15,pointF(400+(xj*20),400+(yj*20)),getRandomColor);
-        inc(xj);
-      end;
-      OnMouseMove := FormMouseMove_SeparateCollision;
-    end;
+procedure TFMain.FreeCircle(aIndex: integer);
+var lKept : TArray<integer>;
+    i : integer;
+begin
+  //Circles above aIndex move down one place : the inspector and the grab keep the same particle.
+  Circles[aIndex].Free;
+  if FGrabbed = aIndex then
+    FGrabbed := -1
+  else if FGrabbed > aIndex then
+    dec(FGrabbed);
+  lKept := nil;
+  for i in FBoxSelection do
+    if i < aIndex then
+      lKept := lKept + [i]
+    else if i > aIndex then
+      lKept := lKept + [i-1];
+  FBoxSelection := lKept;
+  if Selected = aIndex then
+    Selected := -1
+  else if Selected > aIndex then
+    Selected := Selected - 1;
+end;
 
-    DistanceChain: begin
-      CornerButton3.IsPressed := true;
-      SelectionConstraintChainSubMenu.Visible := true;
-      for i := 1 to cst_CHAIN_BALL_COUNT do begin
-        addCircle(15,pointF(400+(i*50),400),getRandomColor);
-        inc(xj);
-      end;
-      OnMouseMove := FormMouseMove_DistanceConstraintChain;
-    end;
+procedure TFMain.PinBoxSelection;
+var lAllPinned : boolean;
+    i : integer;
+begin
+  if length(FBoxSelection) = 0 then
+    exit;
+
+  //Pins the whole group, or unpins it when it is already fully pinned.
+  lAllPinned := true;
+  for i in FBoxSelection do
+    lAllPinned := lAllPinned and TCCircle(Circles[i]).pinned;
+  for i in FBoxSelection do
+    TCCircle(Circles[i]).pinned := not lAllPinned;
+  UpdateInspector;
+  if lAllPinned then
+    ShowStatus(Format('%d particles unpinned',[length(FBoxSelection)]))
+  else
+    ShowStatus(Format('%d particles pinned',[length(FBoxSelection)]));
+end;
+
+procedure TFMain.DeleteBoxSelection;
+var lDeleted : TArray<integer>;
+    i : integer;
+begin
+  //FreeCircle shrinks FBoxSelection : work on a copy, highest index first.
+  lDeleted := Copy(FBoxSelection);
+  for i := length(lDeleted)-1 downto 0 do
+    FreeCircle(lDeleted[i]);
+  PaintBoxGizmos.Repaint;
+  ShowStatus(Format('%d particles deleted',[length(lDeleted)]));
+end;
+
+procedure TFMain.Explode(const aCenter: TPointF);
+var lCircles : TArray<TCCircle>;
+    lDir : TPointF;
+    i : integer;
+begin
+  //No velocities here : particles are displaced radially, then the scene constraints are solved again.
+  lCircles := SceneCircles;
+  for i in CirclesWithin(aCenter,cst_EXPLOSION_RADIUS,1) do begin
+    if lCircles[i].pinned then
+      continue;
+    lDir := lCircles[i].pos - aCenter;
+    if lDir.Length < cst_COINCIDENT_EPSILON then
+      lDir := TConstraintResolver.TieBreak(0,i);
+    lDir.setLength(cst_EXPLOSION_PUSH * (1 - lDir.Length / cst_EXPLOSION_RADIUS));
+    lCircles[i].pos := lCircles[i].pos + lDir;
   end;
+  StepScene;
 end;
 
-{ TCCircle }
+function TFMain.LinkActive(aIndex: integer): boolean;
+begin
+  result := not FCutLinks[aIndex];
+end;
 
-function TCCircle.GetPos: TPointF;
+function TFMain.ChainIsFree: boolean;
+var balls : TArray<TCCircle>;
+    i : integer;
 begin
-  result := Position.Point + PointF(radius,radius);
+  //No cut link and no pin : the chain is a plain FABRIK arm.
+  result := true;
+  balls := ChainBalls;
+  for i := 0 to length(balls)-1 do
+    if balls[i].pinned or ((i > 0) and not LinkActive(i)) then
+      exit(false);
 end;
 
-function TCCircle.GetRadius: single;
+procedure TFMain.CutLinks(const aFrom, aTo: TPointF);
+var balls : TArray<TCCircle>;
+    i : integer;
+    ix,iy : double;
 begin
-  result := Width/2;
+  balls := ChainBalls;
+  for i := 1 to length(balls)-1 do
+    if LinkActive(i) and vIntersect(aFrom.X,aFrom.Y,aTo.X,aTo.Y,
+                                    balls[i-1].pos.X,balls[i-1].pos.Y,balls[i].pos.X,balls[i].pos.Y,ix,iy) then
+      FCutLinks[i] := true;
 end;
 
-procedure TCCircle.SetPos(const Value: TPointF);
+function TFMain.NearestCircle(const aPoint: TPointF; aFirst: integer): integer;
+var lCircles : TArray<TCCircle>;
+    i : integer;
+    lBest : single;
 begin
-  Position.Point := value - pointf(radius,radius);
+  result := -1;
+  lBest := MaxSingle;
+  lCircles := SceneCircles;
+  for i := aFirst to length(lCircles)-1 do
+    if (lCircles[i].pos - aPoint).Length < lBest then begin
+      lBest := (lCircles[i].pos - aPoint).Length;
+      result := i;
+    end;
 end;
 
+function TFMain.CirclesWithin(const aCenter: TPointF; aRadius: single; aFirst: integer): TArray<integer>;
+var lCircles : TArray<TCCircle>;
+    i : integer;
+begin
+  //Centers strictly inside the disc.
+  result := nil;
+  lCircles := SceneCircles;
+  for i := aFirst to length(lCircles)-1 do
+    if (lCircles[i].pos - aCenter).Length < aRadius then
+      result := result + [i];
+end;
 
-procedure TCCircle.setup(aRadius: single; aPos: TPointF; const afillColor : TAlphaColor);
+function TFMain.CirclesInRect(const aRect: TRectF; aFirst: integer): TArray<integer>;
+var lCircles : TArray<TCCircle>;
+    i : integer;
 begin
-  Width := aRadius*2;
-  Height := Width;
-  Fill.Color := aFillColor;
-  pos := aPos;
+  result := nil;
+  lCircles := SceneCircles;
+  for i := aFirst to length(lCircles)-1 do
+    if aRect.Contains(lCircles[i].pos) then
+      result := result + [i];
 end;
 
-{ TPointFTool }
+function TFMain.ChainBalls: TArray<TCCircle>;
+begin
+  //Every circle of the chain scene is a chain ball, from the head (mouse) to the tail (anchor).
+  result := SceneCircles;
+end;
 
-procedure TPointFTool.setLength(aNewLength: single);
-var l  : TDirectionalObject;
-    la : TPt;
+procedure TFMain.AddChainLink;
+var balls : TArray<TCCircle>;
+    lLast : integer;
+    lDir : TPointF;
 begin
-  la := point(X,Y);
-  l := TDirectionalObject.Create(0,0,1);
-  try
-    l.LookAt(la);
-    l.Norm := aNewLength;
-    la := l.GetPointedCoord;
-    X := la.X;
-    Y := la.Y;
-  finally
-    FreeAndNil(l);
+  balls := ChainBalls;
+  lLast := length(balls)-1;
+  if lLast+1 >= cst_CHAIN_BALL_MAX then
+    exit;
+
+  //New tail, in line with the last link.
+  lDir := balls[lLast].pos - balls[lLast-1].pos;
+  if lDir.Length < cst_COINCIDENT_EPSILON then
+    lDir := TConstraintResolver.TieBreak(lLast-1,lLast);
+  ResizeChainLinks(lLast+2);
+  addCircle(balls[lLast].radius,balls[lLast].pos + lDir.Normalize * LinkDistance(lLast+1),getRandomColor);
+  if FTapered then
+    ApplyTaper;
+end;
+
+procedure TFMain.RemoveChainLink;
+var balls : TArray<TCCircle>;
+    lLast : integer;
+begin
+  balls := ChainBalls;
+  lLast := length(balls)-1;
+  if lLast+1 <= cst_CHAIN_BALL_MIN then
+    exit;
+
+  FreeCircle(lLast);
+  ResizeChainLinks(lLast);
+  if FTapered then
+    ApplyTaper;
+end;
+
+procedure TFMain.ResizeChainLinks(aCount: integer);
+var i, lOld : integer;
+begin
+  //New links are whole, at the plain link distance. New joints get the chain-wide limit.
+  lOld := length(FLinkScale);
+  setlength(FCutLinks,aCount);
+  setlength(FLinkScale,aCount);
+  setlength(FJointLimits,aCount);
+  for i := lOld to aCount-1 do begin
+    FCutLinks[i] := false;
+    FLinkScale[i] := 1;
+    FJointLimits[i] := FJointLimit;
   end;
 end;
 
+procedure TFMain.ApplyTaper;
+var balls : TArray<TCCircle>;
+    i, lLast : integer;
+    lScale : single;
+begin
+  //Thick and long at the anchored tail, thin and short at the mouse head, like a whip.
+  balls := ChainBalls;
+  lLast := length(balls)-1;
+  for i := 0 to lLast do begin
+    lScale := 1;
+    if FTapered then
+      lScale := cst_TAPER_END + (1 - cst_TAPER_END) * i / lLast;
+    FLinkScale[i] := lScale;
+    balls[i].radius := cst_CHAIN_BALL_RADIUS * lScale;
+  end;
+end;
+
+function TFMain.LinkDistance(aIndex: integer): single;
+begin
+  result := TrackBar1.Value * FLinkScale[aIndex];
+end;
+
+function TFMain.SceneCircles: TArray<TCCircle>;
+var o : TFmxObject;
+begin
+  result := nil;
+  for o in Children.ToArray do
+    if o is TCCircle then
+      result := result + [TCCircle(o)];
+end;
+
+function TFMain.GetCircles(Index: integer): TCircle;
+var i,c : integer;
+    l : TArray<TFmxObject>;
+begin
+  l := Children.ToArray;
+  c := 0;
+  for I := Low(l) to High(l) do
+    if l[i] is TCircle then begin
+      if c=index then begin
+        result := TCircle(l[i]);
+        break;
+      end;
+      inc(c);
+    end;
+end;
+
+function TFMain.getRandomColor: TAlphaColor;
+begin
+  result := TAlphaColorF.Create(Random(255)/255,Random(255)/255,Random(255)/255,1).ToAlphaColor
+end;
+
+function TFMain.InkColor: TAlphaColor;
+begin
+  //Overlay and status text color readable on the current background.
+  if FTheme in [TBackgroundTheme.Dark,TBackgroundTheme.Gradient] then
+    result := TAlphaColors.Yellow
+  else
+    result := TAlphaColors.Black;
+end;
+
+function TFMain.CaptureFrame: TBitmap;
+begin
+  result := TBitmap.Create(Round(ClientWidth),Round(ClientHeight));
+  if result.Canvas.BeginScene then
+    try
+      PaintTo(result.Canvas);
+    finally
+      result.Canvas.EndScene;
+    end;
+end;
+
+procedure TFMain.SaveScreenshot;
+var lBitmap : TBitmap;
+    lFolder, lFile : string;
+begin
+  lFolder := TPath.Combine(ExtractFilePath(ParamStr(0)),cst_SCREENSHOT_FOLDER);
+  ForceDirectories(lFolder);
+  lFile := TPath.Combine(lFolder,FormatDateTime('yyyymmdd_hhnnss_zzz',Now)+'.png');
+  lBitmap := CaptureFrame;
+  try
+    lBitmap.SaveToFile(lFile);
+  finally
+    FreeAndNil(lBitmap);
+  end;
+  ShowStatus('screenshot : ' + lFile);
+end;
+
+procedure TFMain.ToggleRecording;
+begin
+  if TimerRecord.Enabled then begin
+    TimerRecord.Enabled := false;
+    ShowStatus(Format('%d frames recorded in %s',[FRecordFrame,FRecordFolder]));
+    exit;
+  end;
+
+  FRecordFolder := TPath.Combine(ExtractFilePath(ParamStr(0)),cst_RECORD_FOLDER);
+  FRecordFolder := TPath.Combine(FRecordFolder,FormatDateTime('yyyymmdd_hhnnss',Now));
+  ForceDirectories(FRecordFolder);
+  FRecordFrame := 0;
+  TimerRecord.Enabled := true;
+  ShowStatus('recording, F11 to stop');
+end;
+
+procedure TFMain.TimerSmoothingTimer(Sender: TObject);
+begin
+  FormMouseMove(Self,[],FRawMousePos.X,FRawMousePos.Y);
+end;
+
+procedure TFMain.TimerRecordTimer(Sender: TObject);
+var lFrame, lSmall : TBitmap;
+begin
+  lFrame := CaptureFrame;
+  try
+    lSmall := TBitmap.Create(Round(lFrame.Width*cst_RECORD_SCALE),Round(lFrame.Height*cst_RECORD_SCALE));
+    try
+      if lSmall.Canvas.BeginScene then
+        try
+          lSmall.Canvas.DrawBitmap(lFrame,lFrame.BoundsF,lSmall.BoundsF,1);
+        finally
+          lSmall.Canvas.EndScene;
+        end;
+      inc(FRecordFrame);
+      lSmall.SaveToFile(TPath.Combine(FRecordFolder,Format('frame_%.5d.png',[FRecordFrame])));
+    finally
+      FreeAndNil(lSmall);
+    end;
+  finally
+    FreeAndNil(lFrame);
+  end;
+end;
+
+procedure TFMain.PaintBackground(Canvas: TCanvas);
+var r : TRectF;
+    i,j : integer;
+begin
+  r := PaintBoxOverlay.LocalRect;
+  Canvas.Fill.Kind := TBrushKind.Solid;
+  Canvas.Stroke.Kind := TBrushKind.Solid;
+  Canvas.Stroke.Thickness := 1;
+  case FTheme of
+    TBackgroundTheme.Light : ;
+    TBackgroundTheme.Dark : begin
+      Canvas.Fill.Color := $FF202028;
+      Canvas.FillRect(r,0,0,AllCorners,1);
+    end;
+    TBackgroundTheme.Gradient : begin
+      Canvas.Fill.Kind := TBrushKind.Gradient;
+      Canvas.Fill.Gradient.Color := $FF1C2A4A;
+      Canvas.Fill.Gradient.Color1 := $FF05060A;
+      Canvas.Fill.Gradient.StartPosition.Point := PointF(0.5,0);
+      Canvas.Fill.Gradient.StopPosition.Point := PointF(0.5,1);
+      Canvas.FillRect(r,0,0,AllCorners,1);
+    end;
+    TBackgroundTheme.Grid : begin
+      Canvas.Stroke.Color := $FFE4E4E4;
+      for i := 0 to Trunc(r.Width) div cst_THEME_CELL do
+        Canvas.DrawLine(PointF(i*cst_THEME_CELL,0),PointF(i*cst_THEME_CELL,r.Height),1);
+      for j := 0 to Trunc(r.Height) div cst_THEME_CELL do
+        Canvas.DrawLine(PointF(0,j*cst_THEME_CELL),PointF(r.Width,j*cst_THEME_CELL),1);
+    end;
+    TBackgroundTheme.Checker : begin
+      Canvas.Fill.Color := $FFEDEDED;
+      for i := 0 to Trunc(r.Width) div cst_THEME_CELL do
+        for j := 0 to Trunc(r.Height) div cst_THEME_CELL do
+          if (i+j) mod 2 = 0 then
+            Canvas.FillRect(RectF(i*cst_THEME_CELL,j*cst_THEME_CELL,(i+1)*cst_THEME_CELL,(j+1)*cst_THEME_CELL),0,0,AllCorners,1);
+    end;
+  end;
+end;
+
+procedure TFMain.PaintMotion(Canvas: TCanvas);
+var i : integer;
+    c : TCCircle;
+    lCircles, lTracked : TArray<TCCircle>;
+    lTip, lHead : TPointF;
+begin
+  lCircles := SceneCircles;
+  if length(lCircles)=0 then
+    exit;
+
+  Canvas.Stroke.Kind := TBrushKind.Solid;
+  Canvas.Stroke.Thickness := 1;
+  Canvas.Stroke.Color := InkColor;
+  for c in lCircles do begin
+    if c.velocity.Length<1 then
+      continue;
+    lTip := c.pos + c.velocity * cst_VELOCITY_ARROW_SCALE;
+    lHead := c.velocity.Normalize * 6;
+    Canvas.DrawLine(c.pos,lTip,1);
+    Canvas.DrawLine(lTip,lTip - lHead.Rotate(Pi/6),1);
+    Canvas.DrawLine(lTip,lTip - lHead.Rotate(-Pi/6),1);
+  end;
+
+  //Trails for the mouse driven circle, and the chain tip.
+  lTracked := [lCircles[0]];
+  if FCurrentScene = TConstraintScene.DistanceChain then
+    lTracked := lTracked + [lCircles[length(lCircles)-1]];
+  Canvas.Stroke.Thickness := 2;
+  Canvas.Stroke.Color := TAlphaColors.Darkorange;
+  for c in lTracked do
+    for i := 1 to c.Trail.Count-1 do
+      Canvas.DrawLine(c.Trail.Point(i-1),c.Trail.Point(i),1 - i/c.Trail.Count);
+end;
+
+procedure TFMain.PaintBoxOverlayPaint(Sender: TObject; Canvas: TCanvas);
+var i : integer;
+    balls : TArray<TCCircle>;
+    lStress : single;
+    c : TCCircle;
+    w : TColliderSegment;
+begin
+  PaintBackground(Canvas);
+
+  if FShowMotion then
+    PaintMotion(Canvas);
+
+  //Walls, as thick as the capsule the balls are kept out of.
+  Canvas.Stroke.Kind := TBrushKind.Solid;
+  Canvas.Stroke.Color := InkColor;
+  Canvas.Stroke.Thickness := cst_COLLIDER_HALF_WIDTH*2;
+  Canvas.Stroke.Cap := TStrokeCap.Round;
+  for w in FColliders do
+    Canvas.DrawLine(w.A,w.B,1);
+  Canvas.Stroke.Cap := TStrokeCap.Flat;
+
+  if Selected>=0 then begin
+    c := TCCircle(Circles[Selected]);
+    Canvas.Stroke.Kind := TBrushKind.Solid;
+    Canvas.Stroke.Thickness := 2;
+    Canvas.Stroke.Color := TAlphaColors.Dodgerblue;
+    Canvas.DrawEllipse(RectF(c.pos.X-c.radius-4,c.pos.Y-c.radius-4,c.pos.X+c.radius+4,c.pos.Y+c.radius+4),1);
+  end;
+
+  if FCurrentScene<>TConstraintScene.DistanceChain then
+    exit;
+
+  //Chain links, green at rest length to red when stretched or squeezed.
+  balls := ChainBalls;
+  Canvas.Stroke.Kind := TBrushKind.Solid;
+  Canvas.Stroke.Thickness := 3;
+  for i := 1 to length(balls)-1 do begin
+    if not LinkActive(i) then
+      continue;
+    lStress := LinkStress(i);
+    Canvas.Stroke.Color := TAlphaColorF.Create(lStress,1-lStress,0,1).ToAlphaColor;
+    Canvas.DrawLine(balls[i-1].pos,balls[i].pos,1);
+  end;
+end;
+
+procedure TFMain.PaintBoxGizmosPaint(Sender: TObject; Canvas: TCanvas);
+var c : TCCircle;
+    lCircles : TArray<TCCircle>;
+    i : integer;
+
+  procedure DrawCross(const aCenter : TPointF; aSize : single);
+  begin
+    Canvas.DrawLine(aCenter - PointF(aSize,aSize),aCenter + PointF(aSize,aSize),1);
+    Canvas.DrawLine(aCenter - PointF(aSize,-aSize),aCenter + PointF(aSize,-aSize),1);
+  end;
+
+begin
+  Canvas.Stroke.Kind := TBrushKind.Solid;
+  Canvas.Stroke.Thickness := 2;
+
+  //Mouse target : every scene is driven from there.
+  Canvas.Stroke.Color := InkColor;
+  Canvas.DrawEllipse(RectF(FMousePos.X-4,FMousePos.Y-4,FMousePos.X+4,FMousePos.Y+4),1);
+
+  //Eraser reach around the main circle.
+  if FErasing then begin
+    c := TCCircle(Circles[0]);
+    Canvas.Stroke.Color := TAlphaColors.Red;
+    Canvas.DrawEllipse(RectF(c.pos.X-c.radius-cst_ERASER_REACH,c.pos.Y-c.radius-cst_ERASER_REACH,
+                             c.pos.X+c.radius+cst_ERASER_REACH,c.pos.Y+c.radius+cst_ERASER_REACH),1);
+  end;
+
+  //Rubber band while dragging, then a ring around each selected particle.
+  if FBoxSelecting then begin
+    Canvas.Stroke.Color := TAlphaColors.Dodgerblue;
+    Canvas.Stroke.Dash := TStrokeDash.Dash;
+    Canvas.DrawRect(TRectF.Create(FBoxStart,FBoxEnd,true),0,0,AllCorners,1);
+    Canvas.Stroke.Dash := TStrokeDash.Solid;
+  end;
+  if length(FBoxSelection) > 0 then begin
+    lCircles := SceneCircles;
+    Canvas.Stroke.Color := TAlphaColors.Dodgerblue;
+    for i in FBoxSelection do begin
+      c := lCircles[i];
+      Canvas.DrawEllipse(RectF(c.pos.X-c.radius-3,c.pos.Y-c.radius-3,c.pos.X+c.radius+3,c.pos.Y+c.radius+3),1);
+    end;
+  end;
+
+  //Wall being drawn.
+  if FDrawingCollider then begin
+    Canvas.Stroke.Color := InkColor;
+    Canvas.Stroke.Dash := TStrokeDash.Dash;
+    Canvas.DrawLine(FColliderStart,FColliderEnd,1);
+    Canvas.Stroke.Dash := TStrokeDash.Solid;
+  end;
+
+  //Grab spring, from the cursor to the held particle.
+  if FGrabbed >= 0 then begin
+    c := TCCircle(Circles[FGrabbed]);
+    Canvas.Stroke.Color := TAlphaColors.Dodgerblue;
+    Canvas.Stroke.Dash := TStrokeDash.Dash;
+    Canvas.DrawLine(FMousePos,c.pos,1);
+    Canvas.Stroke.Dash := TStrokeDash.Solid;
+  end;
+
+  //FABRIK anchor, otherwise invisible under the chain tail.
+  if (FCurrentScene = TConstraintScene.DistanceChain) and CheckBoxFabrick.IsChecked then begin
+    Canvas.Stroke.Color := TAlphaColors.Magenta;
+    DrawCross(FFabrikAnchor,10);
+  end;
+
+  //Joints bent to their limit.
+  if FCurrentScene = TConstraintScene.DistanceChain then begin
+    lCircles := SceneCircles;
+    Canvas.Stroke.Color := TAlphaColors.Darkorange;
+    for i := 0 to Min(length(FJointClamped),length(lCircles))-1 do
+      if FJointClamped[i] then begin
+        c := lCircles[i];
+        Canvas.DrawEllipse(RectF(c.pos.X-c.radius-3,c.pos.Y-c.radius-3,c.pos.X+c.radius+3,c.pos.Y+c.radius+3),1);
+      end;
+  end;
+end;
+
+procedure TFMain.PaintBoxProfilerPaint(Sender: TObject; Canvas: TCanvas);
+var i : integer;
+    lTotal, lx, lw : single;
+    r : TRectF;
+begin
+  lTotal := FProfiler.Total;
+  if lTotal<=0 then
+    exit;
+
+  //Stacked bar : each phase takes its share of the whole move.
+  lx := 0;
+  Canvas.Fill.Kind := TBrushKind.Solid;
+  for i := 0 to length(FProfiler.Averages)-1 do begin
+    lw := FProfiler.Averages[i] / lTotal * PaintBoxProfiler.Width;
+    r := RectF(lx,0,lx+lw,PaintBoxProfiler.Height);
+    Canvas.Fill.Color := cst_PROFILER_COLORS[i mod length(cst_PROFILER_COLORS)];
+    Canvas.FillRect(r,0,0,AllCorners,1);
+    lx := lx + lw;
+  end;
+end;
+
+function TFMain.CircleAt(aPoint: TPointF; aFirst: integer): integer;
+var lCircles : TArray<TCCircle>;
+    i : integer;
+begin
+  //Topmost first : the last added circle is drawn above the others.
+  lCircles := SceneCircles;
+  for i := length(lCircles)-1 downto aFirst do
+    if (lCircles[i].pos - aPoint).Length <= lCircles[i].radius then
+      exit(i);
+  result := -1;
+end;
+
+function TFMain.PickCircle(const aPoint: TPointF; aFirst: integer): integer;
+var lMain, c : TCCircle;
+begin
+  //Collision scene : circle 0 sits on the cursor and pushes every free ball out of it, a click only
+  //lands on pinned balls. Fall back on the nearest ball resting against the main circle.
+  if FCurrentScene <> TConstraintScene.SeparateCollision then
+    exit(CircleAt(aPoint,aFirst));
+  result := CircleAt(aPoint,1);
+  if result < 0 then begin
+    lMain := TCCircle(Circles[0]);
+    result := NearestCircle(aPoint,1);
+    if result > 0 then begin
+      c := TCCircle(Circles[result]);
+      if (c.pos - aPoint).Length > lMain.radius + c.radius + cst_PICK_REACH then
+        result := -1;
+    end;
+  end;
+  if (result < 0) and (aFirst = 0) then
+    result := CircleAt(aPoint);
+end;
+
+function TFMain.LinkStress(aIndex: integer): single;
+var balls : TArray<TCCircle>;
+begin
+  balls := ChainBalls;
+  result := Abs((balls[aIndex].pos - balls[aIndex-1].pos).Length - LinkDistance(aIndex)) / LinkDistance(aIndex);
+  result := Min(result / cst_LINK_STRESS_FULLSCALE,1);
+end;
+
+function TFMain.ComputeDiagnostics: TSceneDiagnostics;
+var lCircles : TArray<TCCircle>;
+    i,j,lLinks : integer;
+    lError : single;
+begin
+  result := Default(TSceneDiagnostics);
+  lCircles := SceneCircles;
+
+  for i := 0 to length(lCircles)-1 do
+    result.Kinetic := result.Kinetic + Sqr(lCircles[i].velocity.Length) / 2;
+
+  //Distance constraints : the ball inside the main circle, or the chain links.
+  lLinks := 0;
+  case FCurrentScene of
+    TConstraintScene.basicDistance : begin
+      lError := Max((lCircles[1].pos - lCircles[0].pos).Length - (lCircles[0].radius - lCircles[1].radius),0);
+      result.MaxViolation := lError;
+      result.AvgViolation := lError;
+    end;
+    TConstraintScene.DistanceChain : begin
+      for i := 1 to length(lCircles)-1 do
+        if LinkActive(i) then begin
+          lError := Abs((lCircles[i].pos - lCircles[i-1].pos).Length - LinkDistance(i));
+          result.MaxViolation := Max(result.MaxViolation,lError);
+          result.AvgViolation := result.AvgViolation + lError;
+          inc(lLinks);
+        end;
+      if lLinks > 0 then
+        result.AvgViolation := result.AvgViolation / lLinks;
+    end;
+  end;
+
+  //Overlap only means something where balls are supposed to collide.
+  if (FCurrentScene = TConstraintScene.SeparateCollision)
+     or ((FCurrentScene = TConstraintScene.DistanceChain) and cbBallCollision.IsChecked) then
+    for i := 0 to length(lCircles)-1 do
+      for j := i+1 to length(lCircles)-1 do
+        result.MaxPenetration := Max(result.MaxPenetration,
+                                     lCircles[i].radius + lCircles[j].radius - (lCircles[j].pos - lCircles[i].pos).Length);
+end;
+
+function TFMain.CircleStress(aIndex: integer): single;
+var lCircles : TArray<TCCircle>;
+    i : integer;
+    lOverlap : single;
+begin
+  result := 0;
+  lCircles := SceneCircles;
+  if FCurrentScene = TConstraintScene.DistanceChain then begin
+    //Worst of the links touching this ball.
+    if (aIndex>0) and LinkActive(aIndex) then
+      result := LinkStress(aIndex);
+    if (aIndex<length(lCircles)-1) and LinkActive(aIndex+1) then
+      result := Max(result,LinkStress(aIndex+1));
+  end
+  else begin
+    //Deepest overlap left by the separation pass, relative to the radius.
+    for i := 0 to length(lCircles)-1 do begin
+      if i=aIndex then
+        continue;
+      lOverlap := lCircles[i].radius + lCircles[aIndex].radius - (lCircles[i].pos - lCircles[aIndex].pos).Length;
+      result := Max(result,Min(lOverlap / lCircles[aIndex].radius,1));
+    end;
+  end;
+end;
+
+procedure TFMain.ApplyColorMode;
+var lCircles : TArray<TCCircle>;
+    i,j : integer;
+    t : single;
+begin
+  lCircles := SceneCircles;
+  for i := 0 to length(lCircles)-1 do begin
+    t := 0;
+    case FColorMode of
+      TColorMode.Original : begin
+        lCircles[i].Fill.Color := lCircles[i].baseColor;
+        continue;
+      end;
+      TColorMode.Speed :
+        t := Min(lCircles[i].velocity.Length / cst_SPEED_FULLSCALE,1);
+      TColorMode.Density : begin
+        for j := 0 to length(lCircles)-1 do
+          if (j<>i) and ((lCircles[j].pos - lCircles[i].pos).Length < (lCircles[i].radius + lCircles[j].radius) * 1.5) then
+            t := t + 1;
+        t := Min(t / cst_DENSITY_FULLSCALE,1);
+      end;
+      TColorMode.Stress :
+        t := CircleStress(i);
+    end;
+    //Blue (quiet) to red (hot).
+    lCircles[i].Fill.Color := TAlphaColorF.Create(t,0.2,1-t,1).ToAlphaColor;
+  end;
+end;
+
+procedure TFMain.ShowStatus(const aText: string);
+begin
+  LabelStatus.Text := aText;
+  LabelStatus.Visible := true;
+  LabelStatus.BringToFront;
+  TimerStatus.Enabled := false;
+  TimerStatus.Enabled := true;
+end;
+
+procedure TFMain.TimerStatusTimer(Sender: TObject);
+begin
+  TimerStatus.Enabled := false;
+  LabelStatus.Visible := false;
+end;
+
+function TFMain.ConstraintsOf(aIndex: integer): string;
+var lLast : integer;
+begin
+  result := '';
+  case FCurrentScene of
+    basicDistance :
+      if aIndex=0 then
+        result := 'follows mouse, contains #1'
+      else
+        result := 'stays inside #0';
+    SeparateCollision :
+      if aIndex=0 then
+        result := 'follows mouse, pushes every ball'
+      else
+        result := 'pushed by #0, collides with balls';
+    DistanceChain : begin
+      lLast := length(ChainBalls)-1;
+      if aIndex=0 then
+        result := 'follows mouse';
+      if (aIndex>0) and LinkActive(aIndex) then
+        result := result + Format(', linked to #%d',[aIndex-1]);
+      if (aIndex<lLast) and LinkActive(aIndex+1) then
+        result := result + Format(', linked to #%d',[aIndex+1]);
+      if CheckBoxFabrick.IsChecked and (aIndex=lLast) then
+        result := result + ', FABRIK anchor';
+      if (aIndex>0) and (aIndex<lLast) and (FJointLimits[aIndex] > 0) then
+        result := result + Format(', bends %.0f deg at most',[FJointLimits[aIndex]]);
+      if cbBallCollision.IsChecked then
+        result := result + ', collides with balls';
+    end;
+  end;
+  if TCCircle(Circles[aIndex]).pinned then
+    result := result + ', pinned';
+  if aIndex = FGrabbed then
+    result := result + ', held by the grab spring';
+  if result.StartsWith(', ') then
+    result := result.Substring(2);
+end;
+
+procedure TFMain.SetSelected(const Value: integer);
+begin
+  FSelected := Value;
+  UpdateInspector;
+  PaintBoxOverlay.Repaint;
+end;
+
+procedure TFMain.UpdateInspector;
+var c : TCCircle;
+begin
+  RectangleInspector.Visible := FSelected>=0;
+  if FSelected<0 then
+    exit;
+
+  c := TCCircle(Circles[FSelected]);
+  LabelInspector.Text := Format('particle #%d',[FSelected]) + sLineBreak +
+                         Format('pos : %.1f, %.1f',[c.pos.X,c.pos.Y]) + sLineBreak +
+                         Format('velocity : %.1f, %.1f',[c.velocity.X,c.velocity.Y]) + sLineBreak +
+                         Format('radius : %.1f',[c.radius]) + sLineBreak +
+                         ConstraintsOf(FSelected);
+end;
+
+procedure TFMain.UpdateProfilerOverlay;
+var i : integer;
+    l : string;
+begin
+  if not RectangleProfiler.Visible then
+    exit;
+
+  l := '';
+  for i := 0 to length(FProfiler.Names)-1 do
+    l := l + Format('%s : %.3f ms',[FProfiler.Names[i],FProfiler.Averages[i]]) + sLineBreak;
+  l := l + Format('total : %.3f ms',[FProfiler.Total]) + sLineBreak + sLineBreak;
+  l := l + Format('kinetic : %.1f',[FDiagnostics.Kinetic]) + sLineBreak;
+  l := l + Format('violation : %.2f max, %.2f avg',[FDiagnostics.MaxViolation,FDiagnostics.AvgViolation]) + sLineBreak;
+  l := l + Format('penetration : %.2f max',[FDiagnostics.MaxPenetration]);
+  LabelProfiler.Text := l;
+  PaintBoxProfiler.Repaint;
+end;
+
+function TFMain.SceneToJSON: TJSONObject;
+var lParticles, lCuts, lScales, lWalls, lLimits : TJSONArray;
+    lParticle, lWall : TJSONObject;
+    c : TCCircle;
+    w : TColliderSegment;
+    i : integer;
+begin
+  result := TJSONObject.Create;
+  result.AddPair('scene',cst_SCENE_NAMES[FCurrentScene]);
+  result.AddPair('linkDistance',TJSONNumber.Create(TrackBar1.Value));
+  result.AddPair('fabrik',TJSONBool.Create(CheckBoxFabrick.IsChecked));
+  result.AddPair('ballCollision',TJSONBool.Create(cbBallCollision.IsChecked));
+  result.AddPair('iterations',TJSONNumber.Create(FIterations));
+  result.AddPair('fabrikIterations',TJSONNumber.Create(FFabrikIterations));
+  result.AddPair('anchorX',TJSONNumber.Create(FFabrikAnchor.X));
+  result.AddPair('anchorY',TJSONNumber.Create(FFabrikAnchor.Y));
+
+  lParticles := TJSONArray.Create;
+  result.AddPair('particles',lParticles);
+  for c in SceneCircles do begin
+    lParticle := TJSONObject.Create;
+    lParticle.AddPair('x',TJSONNumber.Create(c.pos.X));
+    lParticle.AddPair('y',TJSONNumber.Create(c.pos.Y));
+    lParticle.AddPair('radius',TJSONNumber.Create(c.radius));
+    lParticle.AddPair('color',IntToHex(Int64(c.baseColor),8));
+    lParticle.AddPair('pinned',TJSONBool.Create(c.pinned));
+    lParticles.AddElement(lParticle);
+  end;
+
+  lCuts := TJSONArray.Create;
+  result.AddPair('cutLinks',lCuts);
+  for i := 1 to length(FCutLinks)-1 do
+    if FCutLinks[i] then
+      lCuts.Add(i);
+
+  if length(FColliders) > 0 then begin
+    lWalls := TJSONArray.Create;
+    result.AddPair('colliders',lWalls);
+    for w in FColliders do begin
+      lWall := TJSONObject.Create;
+      lWall.AddPair('ax',TJSONNumber.Create(w.A.X));
+      lWall.AddPair('ay',TJSONNumber.Create(w.A.Y));
+      lWall.AddPair('bx',TJSONNumber.Create(w.B.X));
+      lWall.AddPair('by',TJSONNumber.Create(w.B.Y));
+      lWalls.AddElement(lWall);
+    end;
+  end;
+
+  if FCurrentScene = TConstraintScene.DistanceChain then begin
+    result.AddPair('tapered',TJSONBool.Create(FTapered));
+    result.AddPair('jointLimit',TJSONNumber.Create(FJointLimit));
+    lLimits := TJSONArray.Create;
+    result.AddPair('jointLimits',lLimits);
+    for i := 0 to length(FJointLimits)-1 do
+      lLimits.AddElement(TJSONNumber.Create(FJointLimits[i]));
+    lScales := TJSONArray.Create;
+    result.AddPair('linkScales',lScales);
+    for i := 0 to length(FLinkScale)-1 do
+      lScales.AddElement(TJSONNumber.Create(FLinkScale[i]));
+  end;
+end;
+
+procedure TFMain.SceneFromJSON(aJSON: TJSONObject);
+var lScene, lSearch : TConstraintScene;
+    lName, lColorText : string;
+    lFound : boolean;
+    lParticles, lCuts, lScales, lWalls, lLimits : TJSONArray;
+    v : TJSONValue;
+    c : TCCircle;
+    w : TColliderSegment;
+    i, lCount, lIterations, lFabrikIterations : integer;
+    lColorValue : Cardinal;
+    lDistance, lX, lY, lRadius, lJointLimit : single;
+    lFabrik, lBallCollision, lTapered : boolean;
+    lAnchor : TPointF;
+    lPositions : TArray<TPointF>;
+    lRadii, lScaleList, lLimitList : TArray<single>;
+    lColors : TArray<TAlphaColor>;
+    lPinned : TArray<boolean>;
+    lCutList : TArray<integer>;
+    lWallList : TArray<TColliderSegment>;
+
+  //Absent optional fields keep their default, present ones must have the right type.
+  function OptionalBool(aObject : TJSONValue; const aName : string; aDefault : boolean) : boolean;
+  var lValue : TJSONValue;
+  begin
+    result := aDefault;
+    lValue := aObject.FindValue(aName);
+    if not Assigned(lValue) then
+      exit;
+    if not (lValue is TJSONBool) then
+      raise ESceneError.CreateFmt('"%s" must be true or false',[aName]);
+    result := TJSONBool(lValue).AsBoolean;
+  end;
+
+  function OptionalNumber(aObject : TJSONValue; const aName : string; aDefault : single) : single;
+  var lValue : TJSONValue;
+  begin
+    result := aDefault;
+    lValue := aObject.FindValue(aName);
+    if not Assigned(lValue) then
+      exit;
+    if not (lValue is TJSONNumber) then
+      raise ESceneError.CreateFmt('"%s" must be a number',[aName]);
+    result := TJSONNumber(lValue).AsDouble;
+  end;
+
+  function OptionalArray(const aName : string) : TJSONArray;
+  var lValue : TJSONValue;
+  begin
+    result := nil;
+    lValue := aJSON.FindValue(aName);
+    if not Assigned(lValue) then
+      exit;
+    if not (lValue is TJSONArray) then
+      raise ESceneError.CreateFmt('"%s" must be an array',[aName]);
+    result := TJSONArray(lValue);
+  end;
+
+begin
+  if not aJSON.TryGetValue<string>('scene',lName) then
+    raise ESceneError.Create('No scene name');
+  lScene := TConstraintScene.basicDistance;
+  lFound := false;
+  for lSearch := Low(TConstraintScene) to High(TConstraintScene) do
+    if SameText(cst_SCENE_NAMES[lSearch],lName) then begin
+      lScene := lSearch;
+      lFound := true;
+    end;
+  if not lFound then
+    raise ESceneError.CreateFmt('Unknown scene "%s"',[lName]);
+
+  if not aJSON.TryGetValue<TJSONArray>('particles',lParticles) then
+    raise ESceneError.Create('No particles array');
+  lCount := lParticles.Count;
+  case lScene of
+    basicDistance : if lCount<>2 then
+      raise ESceneError.Create('Basic scene needs exactly 2 particles');
+    SeparateCollision : if lCount<1 then
+      raise ESceneError.Create('Collision scene needs at least the main circle');
+    DistanceChain : if (lCount<cst_CHAIN_BALL_MIN) or (lCount>cst_CHAIN_BALL_MAX) then
+      raise ESceneError.CreateFmt('Chain scene needs %d to %d particles',[cst_CHAIN_BALL_MIN,cst_CHAIN_BALL_MAX]);
+  end;
+
+  //Everything is read and checked here, before anything is changed.
+  lDistance := OptionalNumber(aJSON,'linkDistance',TrackBar1.Value);
+  if lDistance <= 0 then
+    raise ESceneError.CreateFmt('Link distance must be positive (%.2f)',[lDistance]);
+  lFabrik := OptionalBool(aJSON,'fabrik',CheckBoxFabrick.IsChecked);
+  lBallCollision := OptionalBool(aJSON,'ballCollision',cbBallCollision.IsChecked);
+  lIterations := EnsureRange(Round(OptionalNumber(aJSON,'iterations',FIterations)),1,cst_ITERATIONS_MAX);
+  lFabrikIterations := EnsureRange(Round(OptionalNumber(aJSON,'fabrikIterations',FFabrikIterations)),1,cst_ITERATIONS_MAX);
+  lAnchor := PointF(OptionalNumber(aJSON,'anchorX',FFabrikAnchor.X),OptionalNumber(aJSON,'anchorY',FFabrikAnchor.Y));
+  lTapered := OptionalBool(aJSON,'tapered',false);
+  lJointLimit := OptionalNumber(aJSON,'jointLimit',0);
+  if (lJointLimit < 0) or (lJointLimit > 180) then
+    raise ESceneError.CreateFmt('Joint limit must be 0 to 180 degrees (%.2f)',[lJointLimit]);
+
+  setlength(lPositions,lCount);
+  setlength(lRadii,lCount);
+  setlength(lColors,lCount);
+  setlength(lPinned,lCount);
+  for i := 0 to lCount-1 do begin
+    v := lParticles.Items[i];
+    if not (v.TryGetValue<single>('x',lX) and v.TryGetValue<single>('y',lY)) then
+      raise ESceneError.CreateFmt('Particle %d has no position',[i]);
+    if not v.TryGetValue<single>('radius',lRadius) or (lRadius <= 0) then
+      raise ESceneError.CreateFmt('Particle %d needs a positive radius',[i]);
+    lPositions[i] := PointF(lX,lY);
+    lRadii[i] := lRadius;
+    lColors[i] := TAlphaColors.Gray;
+    if Assigned(v.FindValue('color')) then
+      if v.TryGetValue<string>('color',lColorText) and TryStrToUInt('$'+lColorText,lColorValue) then
+        lColors[i] := TAlphaColor(lColorValue)
+      else
+        raise ESceneError.CreateFmt('Particle %d color must be an AARRGGBB hex string',[i]);
+    lPinned[i] := OptionalBool(v,'pinned',false);
+  end;
+
+  lScaleList := nil;
+  lScales := OptionalArray('linkScales');
+  if Assigned(lScales) then begin
+    if lScales.Count <> lCount then
+      raise ESceneError.CreateFmt('%d link scales for %d particles',[lScales.Count,lCount]);
+    setlength(lScaleList,lCount);
+    lScaleList[0] := 1;
+    for i := 1 to lCount-1 do
+      if not (lScales.Items[i] is TJSONNumber) or (TJSONNumber(lScales.Items[i]).AsDouble <= 0) then
+        raise ESceneError.CreateFmt('Link %d needs a positive scale',[i])
+      else
+        lScaleList[i] := TJSONNumber(lScales.Items[i]).AsDouble;
+  end;
+
+  //Per joint limits, jointLimit alone applies to every joint.
+  lLimitList := nil;
+  lLimits := OptionalArray('jointLimits');
+  if Assigned(lLimits) then begin
+    if lLimits.Count <> lCount then
+      raise ESceneError.CreateFmt('%d joint limits for %d particles',[lLimits.Count,lCount]);
+    setlength(lLimitList,lCount);
+    for i := 0 to lCount-1 do
+      if not (lLimits.Items[i] is TJSONNumber) or (TJSONNumber(lLimits.Items[i]).AsDouble < 0)
+         or (TJSONNumber(lLimits.Items[i]).AsDouble > 180) then
+        raise ESceneError.CreateFmt('Joint %d limit must be 0 to 180 degrees',[i])
+      else
+        lLimitList[i] := TJSONNumber(lLimits.Items[i]).AsDouble;
+  end;
+
+  lCutList := nil;
+  lCuts := OptionalArray('cutLinks');
+  if Assigned(lCuts) and (lCuts.Count > 0) and (lScene <> TConstraintScene.DistanceChain) then
+    raise ESceneError.Create('Only the chain scene has links to cut');
+  if Assigned(lCuts) then
+    for v in lCuts do begin
+      if not (v is TJSONNumber) or not TryStrToInt(v.Value,i) or (i < 1) or (i >= lCount) then
+        raise ESceneError.CreateFmt('Cut link "%s" must be a link index from 1 to %d',[v.Value,lCount-1]);
+      lCutList := lCutList + [i];
+    end;
+
+  lWallList := nil;
+  lWalls := OptionalArray('colliders');
+  if Assigned(lWalls) then begin
+    if lScene <> TConstraintScene.SeparateCollision then
+      raise ESceneError.Create('Only the collision scene has walls');
+    for i := 0 to lWalls.Count-1 do begin
+      v := lWalls.Items[i];
+      if not (v.TryGetValue<single>('ax',w.A.X) and v.TryGetValue<single>('ay',w.A.Y)
+              and v.TryGetValue<single>('bx',w.B.X) and v.TryGetValue<single>('by',w.B.Y)) then
+        raise ESceneError.CreateFmt('Wall %d needs ax, ay, bx and by',[i]);
+      lWallList := lWallList + [w];
+    end;
+  end;
+
+  Scene := lScene;
+  TrackBar1.Value := lDistance;
+  CheckBoxFabrick.IsChecked := lFabrik;
+  cbBallCollision.IsChecked := lBallCollision;
+  FIterations := lIterations;
+  FFabrikIterations := lFabrikIterations;
+  FFabrikAnchor := lAnchor;
+
+  clearScene;
+  for i := 0 to lCount-1 do begin
+    c := TCCircle(addCircle(lRadii[i],lPositions[i],lColors[i]));
+    c.pinned := lPinned[i];
+  end;
+  if lScene = TConstraintScene.DistanceChain then begin
+    //Radii come with the particles, only the rest lengths are restored here.
+    //Start from whole plain links : Scene := may have tapered the previous chain.
+    FLinkScale := nil;
+    FJointLimits := nil;
+    FJointLimit := lJointLimit;
+    ResizeChainLinks(lCount);
+    FTapered := lTapered;
+    if Assigned(lLimitList) then
+      FJointLimits := lLimitList;
+    if Assigned(lScaleList) then
+      FLinkScale := lScaleList
+    else if FTapered then
+      //Hand written "tapered": true without scales : rebuild them (and the radii) from the taper.
+      ApplyTaper;
+  end;
+  FColliders := lWallList;
+  for i in lCutList do
+    FCutLinks[i] := true;
+
+  //Scene := showed the default chain length in the help.
+  UpdateMenus;
+  PaintBoxOverlay.Repaint;
+  PaintBoxGizmos.Repaint;
+end;
+
+procedure TFMain.SaveSceneToFile(const aFileName: string);
+var lJSON : TJSONObject;
+begin
+  lJSON := SceneToJSON;
+  try
+    TFile.WriteAllText(aFileName,lJSON.Format(2));
+  finally
+    FreeAndNil(lJSON);
+  end;
+  ShowStatus('scene saved : ' + aFileName);
+end;
+
+procedure TFMain.LoadSceneFromFile(const aFileName: string);
+begin
+  try
+    SceneFromText(TFile.ReadAllText(aFileName),aFileName);
+  except
+    //Unreadable file, or ESceneError from SceneFromText.
+    on E: Exception do begin
+      ShowMessage('Cannot load the scene.' + sLineBreak + E.Message);
+      exit;
+    end;
+  end;
+  ShowStatus('scene loaded : ' + aFileName);
+end;
+
+procedure TFMain.SceneFromText(const aText, aSource: string);
+var lValue : TJSONValue;
+begin
+  lValue := TJSONObject.ParseJSONValue(aText);
+  try
+    try
+      if not (lValue is TJSONObject) then
+        raise ESceneError.Create('Not a scene JSON object');
+      SceneFromJSON(TJSONObject(lValue));
+    except
+      //Also turns JSON type mismatches into a scene error naming the source.
+      on E: Exception do
+        raise ESceneError.CreateFmt('%s : %s',[aSource,E.Message]);
+    end;
+  finally
+    FreeAndNil(lValue);
+  end;
+end;
+
+procedure TFMain.CopySceneToClipboard;
+var lClipboard : IFMXClipboardService;
+    lJSON : TJSONObject;
+begin
+  if not TPlatformServices.Current.SupportsPlatformService(IFMXClipboardService,lClipboard) then
+    exit;
+  lJSON := SceneToJSON;
+  try
+    lClipboard.SetClipboard(lJSON.Format(2));
+  finally
+    FreeAndNil(lJSON);
+  end;
+  ShowStatus('scene copied to clipboard');
+end;
+
+procedure TFMain.PasteSceneFromClipboard;
+var lClipboard : IFMXClipboardService;
+    lValue : TValue;
+begin
+  if not TPlatformServices.Current.SupportsPlatformService(IFMXClipboardService,lClipboard) then
+    exit;
+  lValue := lClipboard.GetClipboard;
+  if lValue.IsEmpty or not lValue.IsType<string> then begin
+    ShowStatus('clipboard does not hold a scene');
+    exit;
+  end;
+  try
+    SceneFromText(lValue.AsString,'clipboard');
+  except
+    on E: ESceneError do begin
+      ShowMessage('Cannot paste the scene.' + sLineBreak + E.Message);
+      exit;
+    end;
+  end;
+  ShowStatus('scene pasted from clipboard');
+end;
+
+procedure TFMain.SetConstaintScene(const Value: TConstraintScene);
+var i,xj,yj : integer;
+begin
+  CornerButton1.IsPressed := false;
+  CornerButton2.IsPressed := false;
+  CornerButton3.IsPressed := false;
+  FSceneMouseMove := nil;
+  FProfiler.Clear;
+  FCurrentScene := Value;
+  Selected := -1;
+  FGrabbed := -1;
+  FBoxSelecting := false;
+  FBoxSelection := nil;
+  FDrawingCollider := false;
+  FColliders := nil;
+  FSpawning := false;
+  FErasing := false;
+  FCutLinks := nil;
+  FLinkScale := nil;
+  FJointLimits := nil;
+  ResizeChainLinks(cst_CHAIN_BALL_COUNT);
+
+  clearScene;
+  case value  of
+
+    basicDistance: begin
+      CornerButton1.IsPressed := true;
+      addCircle(50,pointF(400,400),TAlphaColors.White);
+      addCircle(15,pointF(400,400),TAlphaColors.Black);
+      FSceneMouseMove := FormMouseMove_DistanceConstraint;
+    end;
+
+    SeparateCollision: begin
+      CornerButton2.IsPressed := true;
+      addCircle(50,pointF(400,400),TAlphaColors.White);
+      xj := 0;
+      yj := xj;
+      for i := 1 to cst_SEPARATECOLL_BALL_COUNT do begin
+        if i Mod 10 = 0 then begin
+          xj := 0;
+          inc(yj);
+        end;
+        addCircle(15,pointF(400+(xj*20),400+(yj*20)),getRandomColor);
+        inc(xj);
+      end;
+      FSceneMouseMove := FormMouseMove_SeparateCollision;
+    end;
+
+    DistanceChain: begin
+      CornerButton3.IsPressed := true;
+      for i := 1 to cst_CHAIN_BALL_COUNT do begin
+        addCircle(cst_CHAIN_BALL_RADIUS,pointF(400+(i*50),400),getRandomColor);
+        inc(xj);
+      end;
+      if FTapered then
+        ApplyTaper;
+      FSceneMouseMove := FormMouseMove_DistanceConstraintChain;
+    end;
+  end;
+  UpdateMenus;
+end;
+
+procedure TFMain.MoveFabrikAnchor(const aOffset: TPointF);
+begin
+  FFabrikAnchor := FFabrikAnchor + aOffset;
+  StepScene;
+end;
+
+procedure TFMain.ApplyGrabSpring;
+var c : TCCircle;
+begin
+  if FGrabbed < 0 then
+    exit;
+  c := TCCircle(Circles[FGrabbed]);
+  if c.pinned then
+    exit;
+  //Zero length spring to the cursor, applied before the scene step : the scene constraints pull back against it.
+  c.pos := c.pos + ((FMousePos - c.pos) * cst_GRAB_STIFFNESS).clampLength(cst_GRAB_MAX_PULL);
+end;
+
+procedure TFMain.UpdateMenus;
+
+  function ChainInfo : string;
+  begin
+    result := '';
+    if FCurrentScene = TConstraintScene.DistanceChain then
+      result := Format(' (%d links)',[length(ChainBalls)-1]);
+  end;
+
+begin
+  //F1 hides every menu panel, the sub menu only belongs to the chain scene.
+  Selection1.Visible := not FHideMenus;
+  SelectionConstraintChainSubMenu.Visible := (not FHideMenus) and (FCurrentScene = TConstraintScene.DistanceChain);
+
+  LabelSceneHelp.Visible := not FHideMenus;
+  LabelSceneHelp.TextSettings.FontColor := InkColor;
+  LabelSceneHelp.Text := cst_SCENE_TITLES[FCurrentScene] + ChainInfo + sLineBreak +
+                         cst_SCENE_DESCRIPTIONS[FCurrentScene] + sLineBreak + sLineBreak +
+                         cst_SCENE_HELP[FCurrentScene];
+end;
+
+{ TCCircle }
+
+function TCCircle.GetPos: TPointF;
+begin
+  result := Position.Point + PointF(radius,radius);
+end;
+
+function TCCircle.GetVelocity: TPointF;
+begin
+  result := pos - FPrevPos;
+end;
+
+function TCCircle.GetAngularVelocity: single;
+begin
+  result := rotation - FPrevRotation;
+end;
+
+function TCCircle.GetRotation: single;
+begin
+  result := RotationAngle;
+end;
+
+procedure TCCircle.SetRotation(const Value: single);
+begin
+  RotationAngle := Value;
+end;
+
+function TCCircle.GetRadius: single;
+begin
+  result := Width/2;
+end;
+
+procedure TCCircle.SetPos(const Value: TPointF);
+begin
+  Position.Point := value - pointf(radius,radius);
+end;
+
+
+procedure TCCircle.setup(aRadius: single; aPos: TPointF; const afillColor : TAlphaColor);
+begin
+  Width := aRadius*2;
+  Height := Width;
+  Fill.Color := aFillColor;
+  FBaseColor := aFillColor;
+  pos := aPos;
+  FPrevPos := aPos;
+  rotation := 0;
+  FPrevRotation := 0;
+  Trail.Clear;
+end;
+
+procedure TCCircle.SetRadius(const Value: single);
+var lCenter : TPointF;
+begin
+  lCenter := pos;
+  Width := Value*2;
+  Height := Width;
+  pos := lCenter;
+end;
+
+procedure TCCircle.SetPinned(const Value: boolean);
+begin
+  FPinned := Value;
+  if FPinned then begin
+    Stroke.Color := TAlphaColors.Red;
+    Stroke.Thickness := 4;
+  end
+  else begin
+    Stroke.Color := TAlphaColors.Black;
+    Stroke.Thickness := 1;
+  end;
+end;
+
+procedure TCCircle.storePrevPos;
+begin
+  FPrevPos := pos;
+  FPrevRotation := rotation;
+end;
+
+{ TTrail }
+
+procedure TTrail.Clear;
+begin
+  setlength(FPoints,cst_TRAIL_LENGTH);
+  FHead := 0;
+  FCount := 0;
+end;
+
+procedure TTrail.Push(const aPoint: TPointF);
+begin
+  FHead := (FHead+1) mod length(FPoints);
+  FPoints[FHead] := aPoint;
+  FCount := Min(FCount+1,length(FPoints));
+end;
+
+function TTrail.Point(aAge: integer): TPointF;
+begin
+  result := FPoints[(FHead - aAge + length(FPoints)) mod length(FPoints)];
+end;
+
+{ TPhaseProfiler }
+
+constructor TPhaseProfiler.Create;
+begin
+  inherited Create;
+  FCurrent := -1;
+end;
+
+procedure TPhaseProfiler.Clear;
+begin
+  FCurrent := -1;
+  FNames := nil;
+  FAverages := nil;
+  FFrame := nil;
+end;
+
+function TPhaseProfiler.PhaseIndex(const aName: string): integer;
+var i : integer;
+begin
+  for i := 0 to length(FNames)-1 do
+    if FNames[i] = aName then
+      exit(i);
+
+  FNames := FNames + [aName];
+  FAverages := FAverages + [0.0];
+  FFrame := FFrame + [0.0];
+  result := length(FNames)-1;
+end;
+
+procedure TPhaseProfiler.BeginPhase(const aName: string);
+begin
+  EndPhase;
+  FCurrent := PhaseIndex(aName);
+  FWatch := TStopwatch.StartNew;
+end;
+
+procedure TPhaseProfiler.EndPhase;
+begin
+  if FCurrent<0 then
+    exit;
+
+  FFrame[FCurrent] := FFrame[FCurrent] + FWatch.Elapsed.TotalMilliseconds;
+  FCurrent := -1;
+end;
+
+procedure TPhaseProfiler.EndFrame;
+var i : integer;
+begin
+  EndPhase;
+  for i := 0 to length(FFrame)-1 do begin
+    FAverages[i] := FAverages[i] * (1-cst_PROFILER_SMOOTHING) + FFrame[i] * cst_PROFILER_SMOOTHING;
+    FFrame[i] := 0;
+  end;
+end;
+
+function TPhaseProfiler.Total: double;
+var a : double;
+begin
+  result := 0;
+  for a in FAverages do
+    result := result + a;
+end;
+
 end.
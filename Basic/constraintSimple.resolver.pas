@@ -0,0 +1,358 @@
+unit constraintSimple.resolver;
+
+//Constraint functions and point helpers, free of any form : the demo scenes and the tests share them.
+
+interface
+
+uses
+  System.SysUtils, System.Types, System.Math,
+  GS.Geometry.Direction;
+
+type
+  //Collision pass order : index order, seeded shuffle per pass, or accumulate-then-apply.
+  TSolverOrder = (Sequential,Shuffled,Jacobi);
+
+  //Add powerfull "pointf.length" (vectored length form paperJs)
+  //DotProduct, CrossProduct, Angle and Rotate are already in TPointF.
+  TPointFTool = record helper for TPointf
+    procedure setLength(aNewLength : single); //vertor grow.
+    function perpendicular : TPointF; //same length, turned a quarter clockwise on screen (y goes down).
+    function clampLength(aMaxLength : single) : TPointF; //shortened to aMaxLength if longer, unchanged otherwise.
+    function lerp(const aTarget : TPointF; aPercent : single) : TPointF; //0 : self, 1 : aTarget.
+    function mul(const aOther : TPointF) : TPointF; //component-wise product.
+  end;
+
+  //A chain of points linked one to the next, from the head (Points[0]) to the tail.
+  TChainState = record
+    Points : TArray<TPointF>;
+    Lengths : TArray<single>; //Lengths[i] : rest length of link i, between points i-1 and i.
+    Locked : TArray<boolean>; //pinned points, never moved.
+    Active : TArray<boolean>; //Active[i] : link i is whole, a cut link constrains nothing.
+    JointLimits : TArray<single>; //JointLimits[i] : radians, how far the link leaving point i may bend. 0 : free.
+    Clamped : TArray<boolean>; //set by SolveChain : joints held at the limit during the last pass.
+    function IsFree : boolean; //no cut link and no locked point.
+    function Reach : single;
+    procedure LimitJoint(aBefore, aJoint, aNext : integer);
+  end;
+
+  //Balls kept apart by the collision pass.
+  TBallState = record
+    Points : TArray<TPointF>;
+    Radii : TArray<single>;
+    Locked : TArray<boolean>; //pinned balls, never moved.
+  end;
+
+  TConstraintResolver = class
+    class function Distance(point, anchor: TPointF; distance: Double): TPointF; overload;
+    class function Distance(point, anchor: TPointF; distance: Double; const aTieBreak : TPointF): TPointF; overload;
+    class function TieBreak(aIndexA, aIndexB : integer) : TPointF;
+    class function LimitAngle(dir, reference : TPointF; maxAngle : Double; out clamped : boolean) : TPointF; //radians.
+    class function InsideCircle(point, center : TPointF; radius : Double) : TPointF;
+    class function InsideAABB(point : TPointF; const rect : TRectF) : TPointF;
+    class function ClosestOnSegment(point, segA, segB : TPointF) : TPointF;
+    class function OutsideCapsule(point, segA, segB : TPointF; thickness : Double) : TPointF; //segment with a radius.
+    //FABRIK passes toward aTarget, tail held on aAnchor when anchored. Returns the passes done, 0 when out of reach.
+    class function SolveChain(var aChain : TChainState; const aTarget : TPointF; aAnchored : boolean;
+                              const aAnchor : TPointF; aIterations : integer; aTolerance : single) : integer;
+    //Moves separating balls a and b, shared when both are free. False if they don't overlap (or are both locked).
+    class function PairCorrection(const aBalls : TBallState; a, b : integer; out aMoveA, aMoveB : TPointF) : boolean;
+    //Pair visiting order : identity, or a shuffle driven by aSeed, which is advanced.
+    class function SolverOrder(aCount : integer; aOrder : TSolverOrder; var aSeed : cardinal) : TArray<integer>;
+    //One collision pass over every pair.
+    class procedure SeparateBalls(var aBalls : TBallState; aOrder : TSolverOrder; var aSeed : cardinal);
+  end;
+
+Const cst_COINCIDENT_EPSILON = 1e-4; //below this, two points are on top of each other and have no direction.
+
+implementation
+
+class function TConstraintResolver.Distance(point, anchor: TPointF; distance: Double): TPointF;
+begin
+  Result := Distance(point,anchor,distance,TieBreak(0,1));
+end;
+
+class function TConstraintResolver.Distance(point, anchor: TPointF; distance: Double; const aTieBreak : TPointF): TPointF;
+begin
+  //Normalize leaves a null vector null : point would be stuck on anchor.
+  if (point - anchor).Length < cst_COINCIDENT_EPSILON then
+    Result := aTieBreak * distance + anchor
+  else
+    Result := (point - anchor).Normalize * distance + anchor;
+end;
+
+class function TConstraintResolver.InsideCircle(point, center: TPointF; radius: Double): TPointF;
+begin
+  //Containment : the point is only moved when it is outside.
+  if (point - center).Length > radius then
+    Result := Distance(point,center,radius)
+  else
+    Result := point;
+end;
+
+class function TConstraintResolver.InsideAABB(point: TPointF; const rect: TRectF): TPointF;
+begin
+  Result := PointF(EnsureRange(point.X,rect.Left,rect.Right),EnsureRange(point.Y,rect.Top,rect.Bottom));
+end;
+
+class function TConstraintResolver.ClosestOnSegment(point, segA, segB: TPointF): TPointF;
+var lSeg : TPointF;
+    t : single;
+begin
+  lSeg := segB - segA;
+  if lSeg.Length < cst_COINCIDENT_EPSILON then
+    exit(segA);
+  t := EnsureRange((point - segA).DotProduct(lSeg) / (lSeg.Length*lSeg.Length),0,1);
+  Result := segA + lSeg * t;
+end;
+
+class function TConstraintResolver.OutsideCapsule(point, segA, segB: TPointF; thickness: Double): TPointF;
+var lClosest, lNormal : TPointF;
+begin
+  //Push the point out along the segment normal (or away from the nearest end).
+  lClosest := ClosestOnSegment(point,segA,segB);
+  if (point - lClosest).Length >= thickness then
+    exit(point);
+  lNormal := (segB - segA).perpendicular;
+  if lNormal.Length < cst_COINCIDENT_EPSILON then
+    lNormal := TieBreak(0,1);
+  Result := Distance(point,lClosest,thickness,lNormal.Normalize);
+end;
+
+class function TConstraintResolver.LimitAngle(dir, reference: TPointF; maxAngle: Double; out clamped: boolean): TPointF;
+var a : Double;
+begin
+  //Signed angle from reference to dir, brought back to +/- maxAngle keeping the length of dir.
+  a := ArcTan2(reference.X*dir.Y - reference.Y*dir.X, reference.X*dir.X + reference.Y*dir.Y);
+  clamped := Abs(a) > maxAngle;
+  if not clamped then
+    exit(dir);
+  a := Sign(a) * maxAngle;
+  Result := reference.Normalize * dir.Length;
+  Result := PointF(Result.X*Cos(a) - Result.Y*Sin(a), Result.X*Sin(a) + Result.Y*Cos(a));
+end;
+
+class function TConstraintResolver.TieBreak(aIndexA, aIndexB: integer): TPointF;
+var lAngle : single;
+begin
+  //Unit vector, always the same for a given pair, spread by the golden angle.
+  lAngle := (aIndexA * 31 + aIndexB) * 2.39996;
+  Result := PointF(Cos(lAngle),Sin(lAngle));
+end;
+
+
+class function TConstraintResolver.SolveChain(var aChain: TChainState; const aTarget: TPointF; aAnchored: boolean;
+  const aAnchor: TPointF; aIterations: integer; aTolerance: single): integer;
+var i, lLast, lPass : integer;
+    lDir : TPointF;
+begin
+  //https://zalo.github.io/blog/constraints/#fabrik-chain
+  lLast := length(aChain.Points)-1;
+  aChain.Clamped := nil;
+  setlength(aChain.Clamped,length(aChain.Points));
+
+  if aAnchored and aChain.IsFree and ((aTarget - aAnchor).Length >= aChain.Reach) then begin
+    //Out of reach : passes would never converge, the best pose is the chain stretched toward the target.
+    lDir := (aTarget - aAnchor).Normalize;
+    aChain.Points[lLast] := aAnchor;
+    for i := lLast downto 1 do
+      aChain.Points[i-1] := aChain.Points[i] + lDir * aChain.Lengths[i];
+    exit(0);
+  end;
+
+  result := 0;
+  for lPass := 1 to aIterations do begin
+    result := lPass;
+    for i := 0 to lLast do
+      aChain.Clamped[i] := false;
+    aChain.Points[0] := aTarget;
+    for i := 1 to lLast do
+      if aChain.Active[i] and not aChain.Locked[i] then begin
+        aChain.Points[i] := Distance(aChain.Points[i],aChain.Points[i-1],aChain.Lengths[i],TieBreak(i-1,i));
+        if (i >= 2) and aChain.Active[i-1] then
+          aChain.LimitJoint(i-2,i-1,i);
+      end;
+
+    //Without an anchor, one pass already satisfies every link.
+    if not aAnchored then
+      break;
+
+    if not aChain.Locked[lLast] then
+      aChain.Points[lLast] := aAnchor;
+    for i := lLast downto 1 do
+      if aChain.Active[i] and not aChain.Locked[i-1] then begin
+        aChain.Points[i-1] := Distance(aChain.Points[i-1],aChain.Points[i],aChain.Lengths[i],TieBreak(i,i-1));
+        if (i < lLast) and aChain.Active[i+1] then
+          aChain.LimitJoint(i+1,i,i-1);
+      end;
+
+    if (aChain.Points[0] - aTarget).Length <= aTolerance then
+      break;
+  end;
+end;
+
+class function TConstraintResolver.PairCorrection(const aBalls: TBallState; a, b: integer; out aMoveA, aMoveB: TPointF): boolean;
+var toNext,lOffset : TPointF;
+    lradius : single;
+begin
+  aMoveA := PointF(0,0);
+  aMoveB := PointF(0,0);
+  toNext := aBalls.Points[b] - aBalls.Points[a];
+  lradius := aBalls.Radii[b] + aBalls.Radii[a];
+  result := not (aBalls.Locked[a] and aBalls.Locked[b]) and (toNext.Length <= lradius);
+  if not result then
+    exit;
+
+  if toNext.Length < cst_COINCIDENT_EPSILON then
+    toNext := TieBreak(a,b);
+  toNext.setLength(lradius);
+  loffset := aBalls.Points[b] - aBalls.Points[a] - toNext;
+  //A locked ball does not move : the other one takes the whole correction.
+  if aBalls.Locked[a] then
+    aMoveB := PointF(0,0) - loffset
+  else if aBalls.Locked[b] then
+    aMoveA := loffset
+  else begin
+    aMoveA := loffset/2;
+    aMoveB := PointF(0,0) - loffset/2;
+  end;
+end;
+
+class function TConstraintResolver.SolverOrder(aCount: integer; aOrder: TSolverOrder; var aSeed: cardinal): TArray<integer>;
+var i,j,lSwap : integer;
+begin
+  setlength(result,aCount);
+  for i := 0 to aCount-1 do
+    result[i] := i;
+  if aOrder <> TSolverOrder.Shuffled then
+    exit;
+
+  //Fisher-Yates on our own generator : the sequence only depends on the seed.
+  for i := aCount-1 downto 1 do begin
+    aSeed := cardinal((UInt64(aSeed) * 1664525 + 1013904223) and $FFFFFFFF);
+    j := aSeed mod cardinal(i+1);
+    lSwap := result[i];
+    result[i] := result[j];
+    result[j] := lSwap;
+  end;
+end;
+
+class procedure TConstraintResolver.SeparateBalls(var aBalls: TBallState; aOrder: TSolverOrder; var aSeed: cardinal);
+var i,j,a,b : integer;
+    lOrder, lContacts : TArray<integer>;
+    lDelta : TArray<TPointF>;
+    lMoveA, lMoveB : TPointF;
+begin
+  //Sequential and shuffled : Gauss-Seidel, each pair sees the previous corrections.
+  //Jacobi : corrections are accumulated from the same positions, then averaged and applied.
+  lOrder := SolverOrder(length(aBalls.Points),aOrder,aSeed);
+  if aOrder = TSolverOrder.Jacobi then begin
+    setlength(lDelta,length(aBalls.Points));
+    setlength(lContacts,length(aBalls.Points));
+  end;
+
+  for i := 0 to length(aBalls.Points)-1 do
+    for j := i+1 to length(aBalls.Points)-1 do begin
+      a := lOrder[i];
+      b := lOrder[j];
+      if not PairCorrection(aBalls,a,b,lMoveA,lMoveB) then
+        continue;
+
+      if aOrder = TSolverOrder.Jacobi then begin
+        lDelta[a] := lDelta[a] + lMoveA;
+        lDelta[b] := lDelta[b] + lMoveB;
+        inc(lContacts[a]);
+        inc(lContacts[b]);
+      end
+      else begin
+        aBalls.Points[a] := aBalls.Points[a] + lMoveA;
+        aBalls.Points[b] := aBalls.Points[b] + lMoveB;
+      end;
+    end;
+
+  if aOrder = TSolverOrder.Jacobi then
+    for i := 0 to length(aBalls.Points)-1 do
+      if lContacts[i] > 0 then
+        aBalls.Points[i] := aBalls.Points[i] + lDelta[i] / lContacts[i];
+end;
+
+{ TChainState }
+
+function TChainState.IsFree: boolean;
+var i : integer;
+begin
+  result := true;
+  for i := 0 to length(Points)-1 do
+    if Locked[i] or ((i > 0) and not Active[i]) then
+      exit(false);
+end;
+
+function TChainState.Reach: single;
+var i : integer;
+begin
+  result := 0;
+  for i := 1 to length(Lengths)-1 do
+    result := result + Lengths[i];
+end;
+
+procedure TChainState.LimitJoint(aBefore, aJoint, aNext: integer);
+var lRef, lDir : TPointF;
+    lClamped : boolean;
+begin
+  //The link leaving aJoint may not bend more than its limit away from the link entering it.
+  if JointLimits[aJoint] <= 0 then
+    exit;
+  lRef := Points[aJoint] - Points[aBefore];
+  lDir := Points[aNext] - Points[aJoint];
+  if (lRef.Length < cst_COINCIDENT_EPSILON) or (lDir.Length < cst_COINCIDENT_EPSILON) then
+    exit;
+
+  lDir := TConstraintResolver.LimitAngle(lDir,lRef,JointLimits[aJoint],lClamped);
+  if lClamped then begin
+    Points[aNext] := Points[aJoint] + lDir;
+    Clamped[aJoint] := true;
+  end;
+end;
+
+{ TPointFTool }
+
+procedure TPointFTool.setLength(aNewLength: single);
+var l  : TDirectionalObject;
+    la : TPt;
+begin
+  la := point(X,Y);
+  l := TDirectionalObject.Create(0,0,1);
+  try
+    l.LookAt(la);
+    l.Norm := aNewLength;
+    la := l.GetPointedCoord;
+    X := la.X;
+    Y := la.Y;
+  finally
+    FreeAndNil(l);
+  end;
+end;
+
+function TPointFTool.perpendicular: TPointF;
+begin
+  result := PointF(-Y,X);
+end;
+
+function TPointFTool.clampLength(aMaxLength: single): TPointF;
+begin
+  result := Self;
+  if result.Length > aMaxLength then
+    result.setLength(aMaxLength);
+end;
+
+function TPointFTool.lerp(const aTarget: TPointF; aPercent: single): TPointF;
+begin
+  result := Self + (aTarget - Self) * aPercent;
+end;
+
+function TPointFTool.mul(const aOther: TPointF): TPointF;
+begin
+  result := PointF(X * aOther.X, Y * aOther.Y);
+end;
+
+
+end.